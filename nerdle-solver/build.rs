@@ -0,0 +1,56 @@
+//! Generate the corpora bundled by the `corpora` feature into `OUT_DIR`, so
+//! they always match the current generator.
+//!
+//! A build script can't depend on its own crate, so this includes the modules
+//! the generator needs directly.
+
+#[cfg(feature = "corpora")]
+extern crate alloc;
+
+#[cfg(feature = "corpora")]
+#[allow(unused_macros)]
+#[macro_use]
+#[path = "src/macros.rs"]
+mod macros;
+#[cfg(feature = "corpora")]
+#[allow(dead_code)]
+#[path = "src/eval.rs"]
+mod eval;
+#[cfg(feature = "corpora")]
+#[allow(dead_code)]
+#[path = "src/gen.rs"]
+mod gen;
+#[cfg(feature = "corpora")]
+#[allow(dead_code)]
+#[path = "src/mask.rs"]
+mod mask;
+
+fn main() {
+    for path in [
+        "build.rs",
+        "src/macros.rs",
+        "src/eval.rs",
+        "src/gen.rs",
+        "src/mask.rs",
+    ] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+
+    #[cfg(feature = "corpora")]
+    {
+        let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+        let mut corpora = vec![
+            ("micro_nerdle.txt", 5, false),
+            ("classic_nerdle.txt", 8, false),
+        ];
+        // Maxi takes minutes to generate and is about 25MB, so it's opt-in
+        if cfg!(feature = "corpora-maxi") {
+            corpora.push(("maxi_nerdle.txt", 10, true));
+        }
+        for (name, slots, extended) in corpora {
+            let mut contents = gen::collect_equations(slots, extended).join("\n");
+            contents.push('\n');
+            std::fs::write(out_dir.join(name), contents).unwrap();
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! Feed arbitrary strings to the evaluator, which should only ever return an
+//! error for malformed input, never panic. Run with `cargo +nightly fuzz run
+//! eval` from the `nerdle-solver` directory.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nerdle_solver::eval;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+    let _ = eval::eval(s);
+    let _ = eval::eval_located(s);
+    let _ = eval::evaluate(s);
+    let _ = eval::eval_with::<i32>(s);
+    let _ = eval::validate_equation(s);
+    let _ = eval::tokenize(s);
+    if let Ok(e) = eval::parse(s) {
+        // The AST has the same value as the string it was parsed from, though
+        // an invalid expression may fail for a different reason
+        assert_eq!(eval::eval_ast(&e).ok(), eval::eval(s).ok());
+        let _ = e.to_string();
+    }
+});
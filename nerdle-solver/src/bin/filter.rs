@@ -1,45 +1,270 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, IsTerminal};
+use std::path::Path;
 
-use rand::{seq::SliceRandom, thread_rng};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 
-use nerdle_solver::mask;
+use nerdle_solver::gen;
+use nerdle_solver::mask::{self, Strategy};
+use nerdle_solver::Solver;
 
 fn prompt(s: &'static str) -> String {
     println!("{}", s);
     let mut line = String::new();
-    std::io::stdin().read_line(&mut line).unwrap();
-    line.trim().to_string()
+    match std::io::stdin().read_line(&mut line) {
+        Ok(0) => std::process::exit(0),
+        Ok(_) => line.trim().to_string(),
+        Err(e) => exit_with(&format!("Couldn't read from stdin: {}", e)),
+    }
+}
+
+// Split a line like `12+34=46 GGBPBBBB` into the guess and, if present, the
+// mask, checking that they're the same length.
+fn split_guess_and_mask(line: &str) -> Result<(String, Option<String>), String> {
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    match parts[..] {
+        [] => Ok((String::new(), None)),
+        [guess] => Ok((guess.to_string(), None)),
+        [guess, mask] => {
+            let guess_len = guess.chars().count();
+            let mask_len = mask.chars().filter(|c| *c != '\u{fe0f}').count();
+            if guess_len != mask_len {
+                return Err(format!(
+                    "The mask {} has {} tiles, but {} has {} chars",
+                    mask, mask_len, guess, guess_len
+                ));
+            }
+            Ok((guess.to_string(), Some(mask.to_string())))
+        }
+        _ => Err("Expected a guess, optionally followed by its mask".to_string()),
+    }
+}
+
+const USAGE: &str =
+    "Usage: filter [corpus|-] [moves] [--hard] [--strategy <entropy|minimax|expected-remaining>] [--seed <n>] [--exact-below <n>] [--sample-size <n>]";
+
+struct Args {
+    // Read from stdin if missing or `-`
+    corpus: Option<String>,
+    // A file of `guess mask` lines to apply instead of prompting
+    moves: Option<String>,
+    hard: bool,
+    strategy: Strategy,
+    // Makes the random choices repeatable
+    seed: Option<u64>,
+    // With at least this many options, only a random sample of
+    // `sample_size` of them is scored
+    exact_below: usize,
+    sample_size: usize,
+}
+
+fn exit_with(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(1);
+}
+
+fn parse_args() -> Args {
+    let mut hard = false;
+    let mut strategy = Strategy::Entropy;
+    let mut seed = None;
+    let mut exact_below = 20_000;
+    let mut sample_size = 1_000;
+    let mut positional = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--hard" => hard = true,
+            "--strategy" => {
+                strategy = match args.next().as_deref() {
+                    Some("entropy") => Strategy::Entropy,
+                    Some("minimax") => Strategy::Minimax,
+                    Some("expected-remaining") => Strategy::ExpectedRemaining,
+                    _ => exit_with(
+                        "--strategy must be one of entropy, minimax, or expected-remaining",
+                    ),
+                }
+            }
+            "--seed" => match args.next().and_then(|s| s.parse().ok()) {
+                Some(s) => seed = Some(s),
+                None => exit_with("--seed must be a non-negative integer"),
+            },
+            "--exact-below" => match args.next().and_then(|s| s.parse().ok()) {
+                Some(n) => exact_below = n,
+                None => exit_with("--exact-below must be a non-negative integer"),
+            },
+            "--sample-size" => match args.next().and_then(|s| s.parse().ok()) {
+                Some(n) if n > 0 => sample_size = n,
+                _ => exit_with("--sample-size must be a positive integer"),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ if arg.starts_with("--") => exit_with(USAGE),
+            _ => positional.push(arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let corpus = positional.next().filter(|c| c != "-");
+    let moves = positional.next();
+    if positional.next().is_some() {
+        exit_with(USAGE);
+    }
+    Args {
+        corpus,
+        moves,
+        hard,
+        strategy,
+        seed,
+        exact_below,
+        sample_size,
+    }
+}
+
+fn open(path: &str) -> BufReader<File> {
+    match File::open(path) {
+        Ok(f) => BufReader::new(f),
+        Err(e) => exit_with(&format!("Couldn't open {}: {}", path, e)),
+    }
+}
+
+fn read_lines(r: impl BufRead, name: &str) -> Vec<String> {
+    r.lines()
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| exit_with(&format!("Couldn't read {}: {}", name, e)))
+}
+
+// Apply each `guess mask` line of the file at `path` in turn, printing how
+// many options are left after each.
+fn run_moves(solver: &mut Solver, path: &str, len: usize) {
+    for (line_no, line) in read_lines(open(path), path).into_iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (guess, Some(mask_txt)) = split_guess_and_mask(&line)
+            .unwrap_or_else(|e| exit_with(&format!("{}:{}: {}", path, line_no + 1, e)))
+        else {
+            exit_with(&format!("{}:{}: missing mask", path, line_no + 1));
+        };
+        if let Err(e) = mask::check_guess(&guess, len) {
+            exit_with(&format!("{}:{}: {}", path, line_no + 1, e));
+        }
+        let m = mask::parse_mask(&guess, &mask_txt)
+            .unwrap_or_else(|e| exit_with(&format!("{}:{}: {}", path, line_no + 1, e)));
+        let solved = m.is_solved(len);
+        solver.apply_masks(m);
+        println!(
+            "{} {}: {} options remaining",
+            guess,
+            mask_txt,
+            solver.remaining_count()
+        );
+        if solved {
+            println!("Solved!");
+            return;
+        }
+    }
+    if let [solution] = solver.remaining() {
+        println!("The solution is {}", solution);
+    }
+}
+
+// The opening guess for the corpus at `path`, if it's one of the corpora with a
+// precomputed opener, e.g. `classic_nerdle.txt`, and hasn't been edited
+fn first_guess(path: Option<&str>, options: &[String], len: usize) -> Option<&'static str> {
+    let name = Path::new(path?).file_stem()?.to_str()?;
+    let guess = mask::precomputed_first_guess(name.strip_suffix("_nerdle")?)?;
+    let full = gen::expected_count(len, false) == Some(options.len());
+    (full && options.iter().any(|o| o == guess)).then_some(guess)
 }
 
 fn main() {
-    let args = std::env::args().collect::<Vec<_>>();
-    print!("Reading options from {}...", args[1]);
-    let f = BufReader::new(File::open(&args[1]).unwrap());
-    let mut options: Vec<String> = f.lines().map(|s| s.unwrap().trim().to_string()).collect();
+    #[cfg(feature = "tracing")]
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+    let Args {
+        corpus,
+        moves,
+        hard,
+        strategy,
+        seed,
+        exact_below,
+        sample_size,
+    } = parse_args();
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let (options, name) = match &corpus {
+        Some(path) => {
+            let f = open(path);
+            print!("Reading options from {}...", path);
+            (gen::read_corpus(f, true), path.as_str())
+        }
+        None => {
+            print!("Reading options from stdin...");
+            (gen::read_corpus(std::io::stdin().lock(), true), "stdin")
+        }
+    };
+    let options = options.unwrap_or_else(|e| exit_with(&format!("Couldn't read {}: {}", name, e)));
     println!("done");
+    if options.is_empty() {
+        exit_with("There are no options to choose from");
+    }
 
+    let len = options.first().map_or(0, |o| o.chars().count());
+    let mut opener = match strategy {
+        Strategy::Entropy => first_guess(corpus.as_deref(), &options, len),
+        _ => None,
+    };
+    let mut solver = Solver::new(options);
+    let mut last_guess: Option<String> = None;
+    // The guesses so far with their feedback, shown in color on a terminal
+    let mut history: Vec<(String, mask::Masks)> = vec![];
+    let color = std::io::stdout().is_terminal();
+    let mut guesses = 0;
+    if let Some(path) = moves {
+        run_moves(&mut solver, &path, len);
+        return;
+    }
     loop {
+        for (guess, m) in &history {
+            if color {
+                println!("{}", mask::render_feedback(guess, m));
+            } else {
+                println!("{} {}", guess, mask::feedback_letters(guess, m));
+            }
+        }
+        let options = solver.remaining();
+        if options.is_empty() {
+            exit_with("No options match the feedback so far; check the masks you entered");
+        }
         println!("{} options remaining", options.len());
         println!();
 
-        println!("Computing best next guess...");
-        let best_guess = if options.len() < 1_000 {
-            let best_guess = mask::compute_best_guess(&options);
-            println!("{}, score: {}", best_guess.0, best_guess.1);
-            best_guess.0.to_string()
+        let best_guess = if let Some(guess) = opener.take() {
+            println!("{}, precomputed best first guess", guess);
+            guess.to_string()
         } else {
-            let subset: Vec<_> = options.choose_multiple(&mut thread_rng(), 500).collect();
-            let best_guess = mask::compute_best_guess(&subset);
-            println!(
-                "{}, score: {} (based on 500 randomly-selected examples)",
-                best_guess.0, best_guess.1
-            );
-            best_guess.0.to_string()
+            println!("Computing best next guess...");
+            let scored: Vec<&String> =
+                if options.len() >= exact_below && options.len() > sample_size {
+                    println!("Scoring a random sample of {} of the options", sample_size);
+                    options.choose_multiple(&mut rng, sample_size).collect()
+                } else {
+                    options.iter().collect()
+                };
+            let top = mask::top_guesses(&scored, 10, strategy);
+            for (guess, score) in &top {
+                println!("{}, score: {}", guess, score);
+            }
+            top[0].0.to_string()
         };
         println!();
 
-        for v in options.choose_multiple(&mut thread_rng(), 25).take(25) {
+        for v in options.choose_multiple(&mut rng, 25).take(25) {
             println!("- {}", v);
         }
         if options.len() > 25 {
@@ -47,27 +272,71 @@ fn main() {
         }
 
         if options.len() == 1 {
+            if let Some(guess) = &last_guess {
+                println!();
+                println!(
+                    "If the solution is {}, {} should have shown {}",
+                    options[0],
+                    guess,
+                    mask::explain(guess, &options[0])
+                );
+            }
             return;
         }
 
         println!();
-        let mut guess = prompt("Enter your guess (you can use s for ² and c for ³)");
-
-        if guess.trim().is_empty() {
-            println!("Using {} as the guess", best_guess);
-            guess = best_guess;
-        }
+        let (guess, mut pasted_mask) = loop {
+            let line = prompt(
+                "Enter your guess (you can use s for ² and c for ³), optionally followed by its mask",
+            );
+            let (guess, mask_txt) = match split_guess_and_mask(&line) {
+                Ok(v) => v,
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            };
+            let guess = if guess.is_empty() {
+                println!("Using {} as the guess", best_guess);
+                best_guess.clone()
+            } else {
+                guess
+            };
+            if let Err(e) = mask::check_guess(&guess, len) {
+                println!("{}", e);
+                continue;
+            }
+            if hard && !mask::satisfies_hard_mode(&guess, solver.known()) {
+                println!("{} doesn't use all of the revealed hints", guess);
+                continue;
+            }
+            break (guess, mask_txt);
+        };
 
-        let mask_txt = loop {
-            let txt =
-                prompt("Enter your mask (G or 2 for green; P or 1 for purple; B or 0 for black)");
-            if !txt.is_empty() {
-                break txt;
+        let m = loop {
+            let txt = match pasted_mask.take() {
+                Some(txt) => txt,
+                None => prompt(
+                    "Enter your mask (G or 2 for green; P or 1 for purple; B or 0 for black; ? if unknown), or paste the tiles",
+                ),
+            };
+            if txt.is_empty() {
+                continue;
+            }
+            match mask::parse_mask(&guess, &txt) {
+                Ok(m) => break m,
+                Err(e) => println!("{}", e),
             }
         };
+        guesses += 1;
 
-        if let Some(m) = mask::parse_mask_results(&guess, &mask_txt) {
-            options.retain(|o| mask::matches_mask(o, &m));
+        if m.is_solved(len) {
+            let plural = if guesses == 1 { "" } else { "es" };
+            println!("Solved in {} guess{}!", guesses, plural);
+            return;
         }
+        solver.apply_masks(m.clone());
+        history.push((guess.clone(), m));
+        last_guess = Some(guess);
     }
 }
@@ -0,0 +1,110 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::{Parser, ValueEnum};
+
+use nerdle_solver::gen;
+
+/// Generate every valid equation with the given number of slots.
+///
+/// The classic, micro and maxi corpora which `filter` reads are generated by
+///
+///   gen --slots 8 --output classic_nerdle.txt --check
+///   gen --slots 5 --output micro_nerdle.txt --check
+///   gen --slots 10 --extended --output maxi_nerdle.txt --check
+#[derive(Parser)]
+#[command(verbatim_doc_comment)]
+struct Args {
+    /// The number of slots in each equation
+    #[arg(long, value_parser = clap::value_parser!(u32).range(3..))]
+    slots: u32,
+    /// Allow parentheses, squares and cubes, as in maxi nerdle
+    #[arg(long, short)]
+    extended: bool,
+    /// Write the equations to this file, compressed if it ends in `.gz`.
+    /// Without it, only the number of equations is printed.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// How to write squares and cubes
+    #[arg(long, value_enum, default_value_t = GlyphStyle::Unicode)]
+    glyph_style: GlyphStyle,
+    /// Fail unless exactly this many equations are generated
+    #[arg(long, conflicts_with = "check")]
+    expect_count: Option<usize>,
+    /// Fail unless the count matches the known count for these options, if
+    /// there is one
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum GlyphStyle {
+    /// ² and ³
+    Unicode,
+    /// s and c
+    Ascii,
+    /// ^2 and ^3
+    Caret,
+}
+
+impl From<GlyphStyle> for gen::GlyphStyle {
+    fn from(style: GlyphStyle) -> Self {
+        match style {
+            GlyphStyle::Unicode => gen::GlyphStyle::Unicode,
+            GlyphStyle::Ascii => gen::GlyphStyle::Ascii,
+            GlyphStyle::Caret => gen::GlyphStyle::Caret,
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    #[cfg(feature = "tracing")]
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+    let args = Args::parse();
+    let slots = args.slots as usize;
+
+    let ct = match &args.output {
+        None => gen::count_equations(slots, args.extended),
+        Some(path) => {
+            let mut ct = 0;
+            let mut f = gen::OutputFile::create(path)
+                .with_context(|| format!("Couldn't create {}", path.display()))?;
+            let mut progress = |ct: usize| {
+                if ct.is_multiple_of(10000) {
+                    #[cfg(feature = "tracing")]
+                    tracing::info!(equations = ct, "progress");
+                    #[cfg(not(feature = "tracing"))]
+                    eprintln!("{} equations", ct);
+                }
+            };
+            gen::gen(
+                slots,
+                &mut gen::styled_line_writer(
+                    &mut f,
+                    &mut ct,
+                    args.glyph_style.into(),
+                    Some(&mut progress),
+                ),
+                args.extended,
+            );
+            f.finish()?;
+            ct
+        }
+    };
+    println!("{}", ct);
+
+    let expected = if args.check {
+        gen::expected_count(slots, args.extended)
+    } else {
+        args.expect_count
+    };
+    if let Some(expected) = expected {
+        if ct != expected {
+            bail!("Expected {} equations, but generated {}", expected, ct);
+        }
+    }
+    Ok(())
+}
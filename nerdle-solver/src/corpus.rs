@@ -0,0 +1,41 @@
+//! The equations for classic and micro nerdle, generated by the build script
+//! and bundled into the library so they can be used without running
+//! the `gen` binary first.
+//!
+//! Maxi nerdle has over 2 million equations (about 25MB), so it's only bundled
+//! with the `corpora-maxi` feature.
+
+use alloc::vec::Vec;
+
+const CLASSIC: &str = include_str!(concat!(env!("OUT_DIR"), "/classic_nerdle.txt"));
+const MICRO: &str = include_str!(concat!(env!("OUT_DIR"), "/micro_nerdle.txt"));
+#[cfg(feature = "corpora-maxi")]
+const MAXI: &str = include_str!(concat!(env!("OUT_DIR"), "/maxi_nerdle.txt"));
+
+/// Every 8-slot classic nerdle equation, as written by `gen --slots 8`
+pub fn classic_corpus() -> Vec<&'static str> {
+    CLASSIC.lines().collect()
+}
+
+/// Every 5-slot micro nerdle equation, as written by `gen --slots 5`
+pub fn micro_corpus() -> Vec<&'static str> {
+    MICRO.lines().collect()
+}
+
+/// Every 10-slot maxi nerdle equation, as written by `gen --slots 10 --extended`
+#[cfg(feature = "corpora-maxi")]
+pub fn maxi_corpus() -> Vec<&'static str> {
+    MAXI.lines().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classic_corpus, micro_corpus};
+
+    #[test]
+    fn test_corpora() {
+        assert_eq!(micro_corpus(), crate::gen::collect_equations(5, false));
+        assert_eq!(classic_corpus().len(), 17_723);
+        assert_eq!(classic_corpus(), crate::gen::collect_equations(8, false));
+    }
+}
@@ -3,51 +3,204 @@
 //!
 //! Nerdle supports the following types:
 //!
-//! - digits 0-9
+//! - digits 0-9 (numbers may not have leading zeros)
 //! - parentheses
 //! - squares ² and cubes ³ (note: we support using `s` and `c`, respectively)
+//! - exponents `^` followed by a single digit, as in some Nerdle variants
+//! - postfix factorials `!`, as in some Nerdle variants
 //! - multiplication * and division /
 //! - addition + and subtraction -
 //!
+//! Squares, cubes, exponents and factorials may be chained, and apply left to
+//! right: `2²³` is `(2²)³`. If one of them overflows, the rest aren't computed
+//! and the whole expression fails with `EvalError::Overflow`.
+//!
+//! Operators bind from tightest to loosest as follows, so `2+3²` is `2+(3²)`
+//! and `6/2²` is `6/(2²)`:
+//!
+//! | Operators          | Associativity                    |
+//! |--------------------|----------------------------------|
+//! | `( )`              |                                  |
+//! | `²` `³` `^n` `!`   | left to right (postfix)          |
+//! | `*` `/`            | left: `12/2/3` is `(12/2)/3`     |
+//! | `+` `-`            | left: `8-3-2` is `(8-3)-2`       |
+//!
+//! A postfix operator only applies to the number or parenthesized expression
+//! right before it; there's no unary minus.
+//!
 //! It also does its internal evaluation using rational numbers when necessary,
-//! since Nerdle permits intermediate fractions during evaluation.
+//! since Nerdle permits intermediate fractions during evaluation. The parser is
+//! generic over the numeric type through the `Val` trait, so `eval_with` can
+//! evaluate using other types too. With the `bigint` feature, `eval` also
+//! retries expressions whose intermediate values overflow `i64` with
+//! arbitrary-precision `BigInt`s.
+//!
+//! This module only needs `core` and `alloc`, so it's available when the crate
+//! is built without the default `std` feature.
 
-use std::str::FromStr;
+use alloc::{boxed::Box, vec::Vec};
+use core::str::FromStr;
 
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::char,
-    character::complete::digit1,
-    combinator::map_res,
-    error::ParseError,
-    multi::fold_many0,
-    sequence::{delimited, pair},
+    character::complete::{char, digit1, satisfy},
+    combinator::{map, map_res, value, verify},
+    error::{FromExternalError, ParseError},
+    multi::{fold_many0, many0},
+    sequence::{delimited, pair, preceded},
     IResult, Parser,
 };
-use num_rational::Rational32;
-use num_traits::{checked_pow, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub};
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+use num_rational::{Rational32, Rational64};
+use num_traits::{checked_pow, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Zero};
 
-fn make_err(i: &str, e: ComputeError) -> nom::Err<nom::error::Error<&str>> {
-    nom::Err::Failure(nom::error::Error::from_error_kind(
-        i,
+/// The ways in which evaluating an expression can fail.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EvalError {
+    /// The input isn't a well-formed expression.
+    ParseFailed,
+    /// An intermediate or final value didn't fit in the numeric type.
+    Overflow,
+    /// The expression divides by zero.
+    DivisionByZero,
+    /// The result isn't an integer.
+    NonIntegerResult,
+    /// A well-formed expression was followed by unparsed input.
+    TrailingInput,
+    /// A factorial was applied to a negative or non-integer value.
+    InvalidFactorial,
+}
+
+impl core::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            EvalError::ParseFailed => "failed to parse expression",
+            EvalError::Overflow => "arithmetic overflow",
+            EvalError::DivisionByZero => "division by zero",
+            EvalError::NonIntegerResult => "result is not an integer",
+            EvalError::TrailingInput => "unexpected input after expression",
+            EvalError::InvalidFactorial => "factorial of a negative or non-integer value",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EvalError {}
+
+impl From<ComputeError> for EvalError {
+    fn from(e: ComputeError) -> Self {
         match e {
-            ComputeError::Compute => nom::error::ErrorKind::Fail,
+            ComputeError::Compute => EvalError::Overflow,
+            ComputeError::DivisionByZero => EvalError::DivisionByZero,
             ComputeError::NonIntegerDivision | ComputeError::NonIntegerResult => {
-                nom::error::ErrorKind::Float
+                EvalError::NonIntegerResult
             }
-        },
-    ))
+            ComputeError::InvalidFactorial => EvalError::InvalidFactorial,
+        }
+    }
+}
+
+impl From<nom::Err<Error<'_>>> for EvalError {
+    fn from(e: nom::Err<Error<'_>>) -> Self {
+        match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => match e.kind {
+                ErrorKind::Parse => EvalError::ParseFailed,
+                ErrorKind::Compute(c) => c.into(),
+            },
+            nom::Err::Incomplete(_) => EvalError::ParseFailed,
+        }
+    }
 }
 
+/// The error type used by the parsers, recording where the failure happened
+/// and whether it was a syntax or an arithmetic failure.
+#[derive(Debug, Clone, PartialEq)]
+struct Error<'a> {
+    input: &'a str,
+    kind: ErrorKind,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ErrorKind {
+    Parse,
+    Compute(ComputeError),
+}
+
+impl<'a> ParseError<&'a str> for Error<'a> {
+    fn from_error_kind(input: &'a str, _: nom::error::ErrorKind) -> Self {
+        Error {
+            input,
+            kind: ErrorKind::Parse,
+        }
+    }
+
+    fn append(_: &'a str, _: nom::error::ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    // Prefer reporting an arithmetic failure over the syntax failure of a later
+    // alternative.
+    fn or(self, other: Self) -> Self {
+        match self.kind {
+            ErrorKind::Compute(_) => self,
+            ErrorKind::Parse => other,
+        }
+    }
+}
+
+// The only external errors come from parsing integer literals, which only fails
+// if the literal is too large to represent.
+impl<'a, E> FromExternalError<&'a str, E> for Error<'a> {
+    fn from_external_error(input: &'a str, _: nom::error::ErrorKind, _: E) -> Self {
+        Error {
+            input,
+            kind: ErrorKind::Compute(ComputeError::Compute),
+        }
+    }
+}
+
+type ParseResult<'a, O> = IResult<&'a str, O, Error<'a>>;
+
+fn make_err(i: &str, e: ComputeError) -> nom::Err<Error<'_>> {
+    nom::Err::Failure(Error {
+        input: i,
+        kind: ErrorKind::Compute(e),
+    })
+}
+
+/// The ways in which a single arithmetic operation on a `Val` can fail.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum ComputeError {
+pub enum ComputeError {
+    /// The operation overflowed or is otherwise undefined.
     Compute,
+    /// The divisor is zero. This is never retried with rational numbers.
+    DivisionByZero,
+    /// The quotient can't be represented exactly by the type. `eval` retries
+    /// the expression with rational numbers when it sees this error.
     NonIntegerDivision,
+    /// The final result isn't an integer.
     NonIntegerResult,
+    /// The operand of a factorial is negative or isn't an integer.
+    InvalidFactorial,
 }
 
-trait Val: Sized + Copy + std::fmt::Debug {
+/// A numeric type which the expression parser can evaluate with.
+///
+/// Implementations must uphold the following contract:
+///
+/// - `from_integer(i)` represents `i` exactly.
+/// - `to_integer(v)` returns `Some(i)` if and only if `v` is exactly equal to
+///   the integer `i`, and `None` otherwise (including when it is out of range
+///   for `i32`).
+/// - `is_natural(v)` returns whether `v` is an integer which is zero or
+///   greater, whether or not it fits in an `i32`.
+/// - the arithmetic operations return the exact result, or an error rather
+///   than a rounded or wrapped value. `DivisionByZero` should be used for a
+///   zero divisor, and `NonIntegerDivision` when a quotient exists but isn't
+///   representable.
+pub trait Val: Sized + Clone + core::fmt::Debug {
     fn add(self, other: Self) -> Result<Self, ComputeError>;
     fn sub(self, other: Self) -> Result<Self, ComputeError>;
     fn mul(self, other: Self) -> Result<Self, ComputeError>;
@@ -55,6 +208,20 @@ trait Val: Sized + Copy + std::fmt::Debug {
     fn pow(self, pow: usize) -> Result<Self, ComputeError>;
     fn to_integer(self) -> Option<i32>;
     fn from_integer(i: i32) -> Self;
+    fn is_natural(&self) -> bool;
+
+    /// Compute the factorial of the value, which must be an integer from 0 to
+    /// 12 (the largest factorial which fits in an `i32`). Larger integers
+    /// overflow; negative or fractional values are `InvalidFactorial`.
+    fn factorial(self) -> Result<Self, ComputeError> {
+        match self.clone().to_integer() {
+            Some(n @ 0..=12) => (2..=n).try_fold(Self::from_integer(1), |acc, k| {
+                acc.mul(Self::from_integer(k))
+            }),
+            _ if !self.is_natural() => Err(ComputeError::InvalidFactorial),
+            _ => Err(ComputeError::Compute),
+        }
+    }
 }
 
 impl Val for i32 {
@@ -69,11 +236,13 @@ impl Val for i32 {
     }
     fn div(self, other: Self) -> Result<Self, ComputeError> {
         if other == 0 {
-            Err(ComputeError::Compute)
-        } else if self % other == 0 {
-            self.checked_div(other).ok_or(ComputeError::Compute)
-        } else {
-            Err(ComputeError::NonIntegerDivision)
+            return Err(ComputeError::DivisionByZero);
+        }
+        // The remainder of `MIN / -1` overflows too, so don't use `%`
+        match self.checked_rem(other) {
+            Some(0) => self.checked_div(other).ok_or(ComputeError::Compute),
+            Some(_) => Err(ComputeError::NonIntegerDivision),
+            None => Err(ComputeError::Compute),
         }
     }
     fn pow(self, pow: usize) -> Result<Self, ComputeError> {
@@ -85,6 +254,44 @@ impl Val for i32 {
     fn from_integer(i: i32) -> Self {
         i
     }
+    fn is_natural(&self) -> bool {
+        *self >= 0
+    }
+}
+
+impl Val for i64 {
+    fn add(self, other: Self) -> Result<Self, ComputeError> {
+        self.checked_add(other).ok_or(ComputeError::Compute)
+    }
+    fn sub(self, other: Self) -> Result<Self, ComputeError> {
+        self.checked_sub(other).ok_or(ComputeError::Compute)
+    }
+    fn mul(self, other: Self) -> Result<Self, ComputeError> {
+        self.checked_mul(other).ok_or(ComputeError::Compute)
+    }
+    fn div(self, other: Self) -> Result<Self, ComputeError> {
+        if other == 0 {
+            return Err(ComputeError::DivisionByZero);
+        }
+        // The remainder of `MIN / -1` overflows too, so don't use `%`
+        match self.checked_rem(other) {
+            Some(0) => self.checked_div(other).ok_or(ComputeError::Compute),
+            Some(_) => Err(ComputeError::NonIntegerDivision),
+            None => Err(ComputeError::Compute),
+        }
+    }
+    fn pow(self, pow: usize) -> Result<Self, ComputeError> {
+        checked_pow(self, pow).ok_or(ComputeError::Compute)
+    }
+    fn to_integer(self) -> Option<i32> {
+        i32::try_from(self).ok()
+    }
+    fn from_integer(i: i32) -> Self {
+        i.into()
+    }
+    fn is_natural(&self) -> bool {
+        !self.is_negative()
+    }
 }
 
 impl Val for Rational32 {
@@ -98,7 +305,11 @@ impl Val for Rational32 {
         self.checked_mul(&other).ok_or(ComputeError::Compute)
     }
     fn div(self, other: Self) -> Result<Self, ComputeError> {
-        self.checked_div(&other).ok_or(ComputeError::Compute)
+        if other.is_zero() {
+            Err(ComputeError::DivisionByZero)
+        } else {
+            self.checked_div(&other).ok_or(ComputeError::Compute)
+        }
     }
     fn pow(self, pow: usize) -> Result<Self, ComputeError> {
         checked_pow(self, pow).ok_or(ComputeError::Compute)
@@ -113,10 +324,95 @@ impl Val for Rational32 {
     fn from_integer(i: i32) -> Self {
         Rational32::from_integer(i)
     }
+    fn is_natural(&self) -> bool {
+        self.is_integer() && *self.numer() >= 0
+    }
+}
+
+impl Val for Rational64 {
+    fn add(self, other: Self) -> Result<Self, ComputeError> {
+        self.checked_add(&other).ok_or(ComputeError::Compute)
+    }
+    fn sub(self, other: Self) -> Result<Self, ComputeError> {
+        self.checked_sub(&other).ok_or(ComputeError::Compute)
+    }
+    fn mul(self, other: Self) -> Result<Self, ComputeError> {
+        self.checked_mul(&other).ok_or(ComputeError::Compute)
+    }
+    fn div(self, other: Self) -> Result<Self, ComputeError> {
+        if other.is_zero() {
+            Err(ComputeError::DivisionByZero)
+        } else {
+            self.checked_div(&other).ok_or(ComputeError::Compute)
+        }
+    }
+    fn pow(self, pow: usize) -> Result<Self, ComputeError> {
+        checked_pow(self, pow).ok_or(ComputeError::Compute)
+    }
+    fn to_integer(self) -> Option<i32> {
+        if Rational64::is_integer(&self) {
+            i32::try_from(Rational64::to_integer(&self)).ok()
+        } else {
+            None
+        }
+    }
+    fn from_integer(i: i32) -> Self {
+        Rational64::from_integer(i.into())
+    }
+    fn is_natural(&self) -> bool {
+        self.is_integer() && *self.numer() >= 0
+    }
+}
+
+// The most bits a `BigInt` value may have. Chained exponents grow so quickly
+// that without a limit a short expression like `2cccccccccccccccccccc` would
+// exhaust memory, and nothing this large can be brought back into `i32` range
+// by an expression of reasonable length.
+#[cfg(feature = "bigint")]
+const BIGINT_MAX_BITS: u64 = 4096;
+
+#[cfg(feature = "bigint")]
+impl Val for BigInt {
+    fn add(self, other: Self) -> Result<Self, ComputeError> {
+        Ok(self + other)
+    }
+    fn sub(self, other: Self) -> Result<Self, ComputeError> {
+        Ok(self - other)
+    }
+    fn mul(self, other: Self) -> Result<Self, ComputeError> {
+        if self.bits() + other.bits() > BIGINT_MAX_BITS {
+            return Err(ComputeError::Compute);
+        }
+        Ok(self * other)
+    }
+    fn div(self, other: Self) -> Result<Self, ComputeError> {
+        if other.is_zero() {
+            Err(ComputeError::DivisionByZero)
+        } else if (&self % &other).is_zero() {
+            Ok(self / other)
+        } else {
+            Err(ComputeError::NonIntegerDivision)
+        }
+    }
+    fn pow(self, pow: usize) -> Result<Self, ComputeError> {
+        if self.bits().saturating_mul(pow as u64) > BIGINT_MAX_BITS {
+            return Err(ComputeError::Compute);
+        }
+        Ok(num_traits::Pow::pow(self, pow as u32))
+    }
+    fn to_integer(self) -> Option<i32> {
+        i32::try_from(self).ok()
+    }
+    fn from_integer(i: i32) -> Self {
+        i.into()
+    }
+    fn is_natural(&self) -> bool {
+        self.sign() != num_bigint::Sign::Minus
+    }
 }
 
 // We parse any expr surrounded by parens
-fn parens<V: Val>(i: &str) -> IResult<&str, V> {
+fn parens<V: Val>(i: &str) -> ParseResult<'_, V> {
     delimited(tag("("), expr, tag(")")).parse(i)
 }
 
@@ -124,26 +420,32 @@ fn parens<V: Val>(i: &str) -> IResult<&str, V> {
 // We look for a digit suite, and try to convert it.
 // If either str::from_utf8 or FromStr::from_str fail,
 // we fallback to the parens parser defined above
-fn factor<V: Val>(i: &str) -> IResult<&str, V> {
+fn factor<V: Val>(i: &str) -> ParseResult<'_, V> {
     alt((
-        map_res(digit1, |s| i32::from_str(s).map(|v| V::from_integer(v))),
+        map_res(literal, |s| i32::from_str(s).map(|v| V::from_integer(v))),
         parens,
     ))
     .parse(i)
 }
 
-// We apply any number of squares or cubes to a `factor`, which might be a
-// parenthesized expression
-fn exponent<V: Val>(i: &str) -> IResult<&str, V> {
-    let (i, init) = factor(i)?;
+// A run of digits without leading zeros, which Nerdle doesn't allow (a bare `0`
+// is fine)
+fn literal(i: &str) -> ParseResult<'_, &str> {
+    verify(digit1, |s: &str| s == "0" || !s.starts_with('0')).parse(i)
+}
+
+// We apply any number of squares, cubes, single-digit `^` exponents, or
+// factorials to a `factor`, which might be a parenthesized expression. These
+// are applied left to right, so `2^3^2` is `(2^3)^2` and `3!²` is `(3!)²`.
+fn exponent<V: Val>(i: &str) -> ParseResult<'_, V> {
+    let (i, init) = factor::<V>(i)?;
     fold_many0(
-        alt((char('²'), char('s'), char('³'), char('c'))),
-        move || Ok(init),
-        |acc, op: char| {
+        postfix,
+        move || Ok(init.clone()),
+        |acc, op: Postfix| {
             acc.and_then(|acc: V| match op {
-                '²' | 's' => acc.pow(2),
-                '³' | 'c' => acc.pow(3),
-                _ => unreachable!(),
+                Postfix::Pow(pow) => acc.pow(pow as usize),
+                Postfix::Factorial => acc.factorial(),
             })
         },
     )
@@ -151,15 +453,37 @@ fn exponent<V: Val>(i: &str) -> IResult<&str, V> {
     .and_then(|(x, v)| v.map(|v| (x, v)).map_err(|e| make_err(i, e)))
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Postfix {
+    Pow(u32),
+    Factorial,
+}
+
+// A single square, cube, `^` exponent, or factorial
+fn postfix(i: &str) -> ParseResult<'_, Postfix> {
+    alt((
+        value(Postfix::Pow(2), alt((char('²'), char('s')))),
+        value(Postfix::Pow(3), alt((char('³'), char('c')))),
+        preceded(
+            char('^'),
+            map(satisfy(|c| c.is_ascii_digit()), |c| {
+                Postfix::Pow(c.to_digit(10).unwrap())
+            }),
+        ),
+        value(Postfix::Factorial, char('!')),
+    ))
+    .parse(i)
+}
+
 // We read an initial factor and for each time we find
 // a * or / operator followed by another factor, we do
 // the math by folding everything
-fn term<V: Val>(i: &str) -> IResult<&str, V> {
-    let (i, init) = exponent(i)?;
+fn term<V: Val>(i: &str) -> ParseResult<'_, V> {
+    let (i, init) = exponent::<V>(i)?;
 
     fold_many0(
         pair(alt((char('*'), char('/'))), exponent),
-        move || Ok(init),
+        move || Ok(init.clone()),
         |acc, (op, val): (char, V)| {
             acc.and_then(|acc: V| {
                 if op == '*' {
@@ -174,12 +498,12 @@ fn term<V: Val>(i: &str) -> IResult<&str, V> {
     .and_then(|(x, v)| v.map(|v| (x, v)).map_err(|e| make_err(i, e)))
 }
 
-fn expr<V: Val>(i: &str) -> IResult<&str, V> {
-    let (i, init) = term(i)?;
+fn expr<V: Val>(i: &str) -> ParseResult<'_, V> {
+    let (i, init) = term::<V>(i)?;
 
     fold_many0(
         pair(alt((char('+'), char('-'))), term),
-        move || Ok(init),
+        move || Ok(init.clone()),
         |acc, (op, val): (char, V)| {
             acc.and_then(|acc: V| {
                 if op == '+' {
@@ -195,23 +519,475 @@ fn expr<V: Val>(i: &str) -> IResult<&str, V> {
 }
 
 /// Evaluate the provided string, returning an integer result or an error.
-pub fn eval(i: &str) -> Result<i32, nom::Err<nom::error::Error<&str>>> {
-    match expr::<i32>(i) {
-        Ok((rem, v)) if rem.is_empty() => Ok(v),
-        Err(nom::Err::Failure(f)) if f.code == nom::error::ErrorKind::Float => {
-            let (rem, v) = expr::<Rational32>(i)?;
-            if !rem.is_empty() {
-                Err(make_err(i, ComputeError::Compute))?
+/// Squares and cubes may be written either as ² and ³ or as `s` and `c`.
+///
+/// The expression is evaluated with `i64` so that intermediate values may
+/// exceed the range of `i32`, falling back to rational numbers only if a
+/// division doesn't produce an integer. Rationals are tried with 32-bit parts
+/// first, then 64-bit parts if those overflow. With the `bigint` feature, an
+/// expression which overflows `i64` is retried with `BigInt`. The final result
+/// must fit in an `i32`.
+pub fn eval(i: &str) -> Result<i32, EvalError> {
+    eval_located(i).map_err(|(e, _)| e)
+}
+
+/// Evaluate the provided string like `eval`, but on failure also return the
+/// byte offset into `i` at which evaluation failed. Failures of the expression
+/// as a whole, such as a non-integer result, are reported at offset 0.
+pub fn eval_located(i: &str) -> Result<i32, (EvalError, usize)> {
+    match expr::<i64>(i) {
+        Ok(("", v)) => i32::try_from(v).map_err(|_| (EvalError::Overflow, 0)),
+        Ok((rem, _)) => Err((EvalError::TrailingInput, i.len() - rem.len())),
+        Err(nom::Err::Failure(Error {
+            kind: ErrorKind::Compute(ComputeError::NonIntegerDivision),
+            ..
+        })) => rational_to_integer(|| eval_with_located(i), || eval_with_located(i)),
+        #[cfg(feature = "bigint")]
+        Err(nom::Err::Failure(Error {
+            kind: ErrorKind::Compute(ComputeError::Compute),
+            ..
+        })) => eval_bigint_located(i),
+        Err(e) => Err(locate(i, e)),
+    }
+}
+
+// Evaluate an expression which overflowed `i64` with `BigInt`, falling back to
+// rational numbers as `eval_located` does if a division isn't exact
+#[cfg(feature = "bigint")]
+fn eval_bigint_located(i: &str) -> Result<i32, (EvalError, usize)> {
+    match eval_with_located::<BigInt>(i) {
+        Ok(v) => v.to_integer().ok_or((EvalError::Overflow, 0)),
+        Err((EvalError::NonIntegerResult, _)) => {
+            rational_to_integer(|| eval_with_located(i), || eval_with_located(i))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Convert a parser error to an `EvalError` and its offset into `i`
+fn locate<'a>(i: &'a str, e: nom::Err<Error<'a>>) -> (EvalError, usize) {
+    let offset = match &e {
+        nom::Err::Error(e) | nom::Err::Failure(e) => i.len() - e.input.len(),
+        nom::Err::Incomplete(_) => i.len(),
+    };
+    (e.into(), offset)
+}
+
+// Convert the result of a rational evaluation to an integer, retrying with
+// `Rational64` if the `Rational32` evaluation overflowed. If the result still
+// doesn't fit in an `i32`, the overflow is reported where `Rational32` found it.
+fn rational_to_integer(
+    r32: impl FnOnce() -> Result<Rational32, (EvalError, usize)>,
+    r64: impl FnOnce() -> Result<Rational64, (EvalError, usize)>,
+) -> Result<i32, (EvalError, usize)> {
+    let v = match r32() {
+        Err((EvalError::Overflow, offset)) => {
+            let v = r64()?;
+            if v.is_integer() {
+                return i32::try_from(Rational64::to_integer(&v))
+                    .map_err(|_| (EvalError::Overflow, offset));
             }
+            None
+        }
+        v => Val::to_integer(v?),
+    };
+    v.ok_or((EvalError::NonIntegerResult, 0))
+}
+
+/// Evaluate a literal board row, such as a user's guess, failing to parse if it
+/// contains any whitespace.
+///
+/// `eval` never skips whitespace either, but depending on where it appears it
+/// may be reported as trailing input; this always reports a parse failure.
+pub fn eval_strict(i: &str) -> Result<i32, EvalError> {
+    if i.contains(char::is_whitespace) {
+        return Err(EvalError::ParseFailed);
+    }
+    eval(i)
+}
+
+/// Evaluate the provided string, returning the exact rational result (in lowest
+/// terms) or an error. Unlike `eval`, the result need not be an integer.
+pub fn eval_rational(i: &str) -> Result<Rational32, EvalError> {
+    eval_with(i)
+}
 
-            v.to_integer()
-                .ok_or_else(|| make_err(i, ComputeError::NonIntegerResult))
+/// The detailed result of `evaluate`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Evaluation {
+    /// The value of the expression, if it's an integer.
+    pub integer: Option<i32>,
+    /// The exact value of the expression.
+    pub rational: Rational32,
+    /// Whether evaluating with `i32` overflowed before the expression needed
+    /// to be retried with rational numbers.
+    pub overflowed_i32: bool,
+}
+
+/// Evaluate the provided string both as integers and as rationals, exposing the
+/// details which `eval` hides. Fails if the expression can't be evaluated, or if
+/// its value isn't an integer and can't be computed as a `Rational32`.
+pub fn evaluate(i: &str) -> Result<Evaluation, EvalError> {
+    let rational = match eval_rational(i) {
+        // Intermediate values may overflow even though the result is an integer
+        Err(EvalError::Overflow) => Rational32::from_integer(eval(i)?),
+        r => r?,
+    };
+    Ok(Evaluation {
+        integer: Val::to_integer(rational),
+        rational,
+        overflowed_i32: eval_with::<i32>(i) == Err(EvalError::Overflow),
+    })
+}
+
+/// Evaluate the provided string using the numeric type `V`, returning the value
+/// of the whole expression or an error.
+pub fn eval_with<V: Val>(i: &str) -> Result<V, EvalError> {
+    eval_with_located(i).map_err(|(e, _)| e)
+}
+
+fn eval_with_located<V: Val>(i: &str) -> Result<V, (EvalError, usize)> {
+    match expr::<V>(i) {
+        Ok(("", v)) => Ok(v),
+        Ok((rem, _)) => Err((EvalError::TrailingInput, i.len() - rem.len())),
+        Err(e) => Err(locate(i, e)),
+    }
+}
+
+/// The number of characters needed to write `v` in decimal, including the `-`
+/// sign if it's negative.
+pub fn decimal_width(v: i32) -> usize {
+    let digits = v.unsigned_abs().checked_ilog10().unwrap_or(0) as usize + 1;
+    if v < 0 {
+        digits + 1
+    } else {
+        digits
+    }
+}
+
+/// Which operators `eval_config` accepts, for Nerdle variants which don't allow
+/// all of them. The default allows everything.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct EvalConfig {
+    pub allow_div: bool,
+    pub allow_mul: bool,
+    pub allow_square: bool,
+    pub allow_cube: bool,
+    pub allow_paren: bool,
+    pub allow_exponent: bool,
+    pub allow_factorial: bool,
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        EvalConfig {
+            allow_div: true,
+            allow_mul: true,
+            allow_square: true,
+            allow_cube: true,
+            allow_paren: true,
+            allow_exponent: true,
+            allow_factorial: true,
         }
-        Ok(_) => Err(make_err(i, ComputeError::Compute)),
+    }
+}
+
+impl EvalConfig {
+    fn allows(&self, c: char) -> bool {
+        match c {
+            '/' => self.allow_div,
+            '*' => self.allow_mul,
+            '²' | 's' => self.allow_square,
+            '³' | 'c' => self.allow_cube,
+            '(' | ')' => self.allow_paren,
+            '^' => self.allow_exponent,
+            '!' => self.allow_factorial,
+            _ => true,
+        }
+    }
+}
+
+/// Evaluate the provided string like `eval`, but fail to parse it if it uses
+/// any operator which `cfg` disallows.
+pub fn eval_config(i: &str, cfg: &EvalConfig) -> Result<i32, EvalError> {
+    if !i.chars().all(|c| cfg.allows(c)) {
+        return Err(EvalError::ParseFailed);
+    }
+    eval(i)
+}
+
+/// Check whether a complete equation such as `12+34=46` holds, evaluating both
+/// sides exactly. Returns an error if either side fails to evaluate, or if the
+/// input doesn't contain exactly one `=`.
+///
+/// Like `eval`, a side whose intermediate values overflow is retried with
+/// wider types, so `50000*50000/50000=50000` holds.
+pub fn validate_equation(s: &str) -> Result<bool, EvalError> {
+    let mut sides = s.split('=');
+    match (sides.next(), sides.next(), sides.next()) {
+        (Some(lhs), Some(rhs), None) => Ok(exact_value(lhs)? == exact_value(rhs)?),
+        _ => Err(EvalError::ParseFailed),
+    }
+}
+
+// The exact value of one side of an equation or relation. This tries
+// `Rational32` first, since it's fastest, then `Rational64`, and then `eval`'s
+// fallbacks, which handle any integer result.
+fn exact_value(i: &str) -> Result<Rational64, EvalError> {
+    match eval_rational(i) {
+        Ok(v) => Ok(Rational64::new((*v.numer()).into(), (*v.denom()).into())),
+        Err(EvalError::Overflow) => match eval_with::<Rational64>(i) {
+            Err(EvalError::Overflow) => Ok(Rational64::from_integer(eval(i)?.into())),
+            r => r,
+        },
         Err(e) => Err(e),
     }
 }
 
+/// Like `validate_equation`, but also accepts an inequality such as `1+2<4`,
+/// with exactly one `=`, `<`, or `>` between the two sides.
+pub fn validate_relation(s: &str) -> Result<bool, EvalError> {
+    let mut relations = s.match_indices(['=', '<', '>']);
+    match (relations.next(), relations.next()) {
+        (Some((i, rel)), None) => {
+            let lhs = exact_value(&s[..i])?;
+            let rhs = exact_value(&s[i + 1..])?;
+            Ok(match rel {
+                "<" => lhs < rhs,
+                ">" => lhs > rhs,
+                _ => lhs == rhs,
+            })
+        }
+        _ => Err(EvalError::ParseFailed),
+    }
+}
+
+/// A binary operator in an `Expr`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl core::fmt::Display for Op {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+        })
+    }
+}
+
+/// The syntax tree of an expression, as produced by `parse`.
+///
+/// Parentheses are kept as `Paren` nodes so that the tree displays as the
+/// expression it was parsed from (with squares and cubes shown as ² and ³).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Expr {
+    Num(i32),
+    BinOp(Box<Expr>, Op, Box<Expr>),
+    Pow(Box<Expr>, u32),
+    Factorial(Box<Expr>),
+    Paren(Box<Expr>),
+}
+
+impl core::fmt::Display for Expr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::BinOp(l, op, r) => write!(f, "{}{}{}", l, op, r),
+            Expr::Pow(e, 2) => write!(f, "{}²", e),
+            Expr::Pow(e, 3) => write!(f, "{}³", e),
+            Expr::Pow(e, n) => write!(f, "{}^{}", e, n),
+            Expr::Factorial(e) => write!(f, "{}!", e),
+            Expr::Paren(e) => write!(f, "({})", e),
+        }
+    }
+}
+
+/// Parse the provided string into an `Expr`, using the same grammar as `eval`.
+pub fn parse(i: &str) -> Result<Expr, EvalError> {
+    match ast_expr(i)? {
+        ("", e) => Ok(e),
+        _ => Err(EvalError::TrailingInput),
+    }
+}
+
+/// Evaluate a parsed expression, returning an integer result or an error. This
+/// gives the same result as `eval` on the string the expression was parsed
+/// from, except that if it has several problems the error may be for a
+/// different one: `eval` stops parsing at `6/0/21!`'s out-of-range factorial,
+/// but the tree is evaluated left to right, so this finds the division by zero
+/// first.
+pub fn eval_ast(e: &Expr) -> Result<i32, EvalError> {
+    match eval_node::<i64>(e) {
+        Ok(v) => i32::try_from(v).map_err(|_| EvalError::Overflow),
+        Err(ComputeError::NonIntegerDivision) => rational_to_integer(
+            || eval_node(e).map_err(|c| (c.into(), 0)),
+            || eval_node(e).map_err(|c| (c.into(), 0)),
+        )
+        .map_err(|(e, _)| e),
+        #[cfg(feature = "bigint")]
+        Err(ComputeError::Compute) => match eval_node::<BigInt>(e) {
+            Ok(v) => v.to_integer().ok_or(EvalError::Overflow),
+            Err(ComputeError::NonIntegerDivision) => rational_to_integer(
+                || eval_node(e).map_err(|c| (c.into(), 0)),
+                || eval_node(e).map_err(|c| (c.into(), 0)),
+            )
+            .map_err(|(e, _)| e),
+            Err(e) => Err(e.into()),
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Evaluate a parsed expression exactly, returning its rational value (in
+/// lowest terms) or an error. Like `eval_rational`, the result need not be an
+/// integer.
+pub fn eval_ast_rational(e: &Expr) -> Result<Rational32, EvalError> {
+    Ok(eval_node(e)?)
+}
+
+/// Evaluate a parsed expression exactly like `eval_ast_rational`, returning the
+/// value of each operation along the way, in the order they're computed. This
+/// shows the working for an equation, e.g. `2/3+1/3` gives `2/3` for `2/3`,
+/// `1/3` for `1/3` and `1` for the whole expression.
+///
+/// Numbers and parentheses aren't operations, so they aren't included.
+pub fn eval_ast_steps(e: &Expr) -> Result<Vec<(Expr, Rational32)>, EvalError> {
+    let mut steps = Vec::new();
+    eval_node_with(e, &mut |e: &Expr, v: &Rational32| {
+        if !matches!(e, Expr::Num(_) | Expr::Paren(_)) {
+            steps.push((e.clone(), *v));
+        }
+    })?;
+    Ok(steps)
+}
+
+fn eval_node<V: Val>(e: &Expr) -> Result<V, ComputeError> {
+    eval_node_with(e, &mut |_: &Expr, _: &V| ())
+}
+
+// Evaluate `e`, passing each node and its value to `visit` once it has been
+// computed, so children come before their parents
+fn eval_node_with<V: Val>(e: &Expr, visit: &mut impl FnMut(&Expr, &V)) -> Result<V, ComputeError> {
+    let v = match e {
+        Expr::Num(n) => Ok(V::from_integer(*n)),
+        Expr::BinOp(l, op, r) => {
+            let (l, r) = (eval_node_with(l, visit)?, eval_node_with(r, visit)?);
+            match op {
+                Op::Add => l.add(r),
+                Op::Sub => l.sub(r),
+                Op::Mul => l.mul(r),
+                Op::Div => l.div(r),
+            }
+        }
+        Expr::Pow(e, n) => eval_node_with(e, visit)?.pow(*n as usize),
+        Expr::Factorial(e) => eval_node_with(e, visit)?.factorial(),
+        Expr::Paren(e) => eval_node_with(e, visit),
+    }?;
+    visit(e, &v);
+    Ok(v)
+}
+
+fn ast_factor(i: &str) -> ParseResult<'_, Expr> {
+    alt((
+        map_res(literal, |s| i32::from_str(s).map(Expr::Num)),
+        map(delimited(tag("("), ast_expr, tag(")")), |e| {
+            Expr::Paren(Box::new(e))
+        }),
+    ))
+    .parse(i)
+}
+
+fn ast_exponent(i: &str) -> ParseResult<'_, Expr> {
+    let (i, init) = ast_factor(i)?;
+    let (i, ops) = many0(postfix).parse(i)?;
+    Ok((
+        i,
+        ops.into_iter().fold(init, |acc, op| match op {
+            Postfix::Pow(pow) => Expr::Pow(Box::new(acc), pow),
+            Postfix::Factorial => Expr::Factorial(Box::new(acc)),
+        }),
+    ))
+}
+
+fn ast_term(i: &str) -> ParseResult<'_, Expr> {
+    let (i, init) = ast_exponent(i)?;
+    let (i, rest) = many0(pair(
+        alt((value(Op::Mul, char('*')), value(Op::Div, char('/')))),
+        ast_exponent,
+    ))
+    .parse(i)?;
+    Ok((
+        i,
+        rest.into_iter().fold(init, |acc, (op, e)| {
+            Expr::BinOp(Box::new(acc), op, Box::new(e))
+        }),
+    ))
+}
+
+fn ast_expr(i: &str) -> ParseResult<'_, Expr> {
+    let (i, init) = ast_term(i)?;
+    let (i, rest) = many0(pair(
+        alt((value(Op::Add, char('+')), value(Op::Sub, char('-')))),
+        ast_term,
+    ))
+    .parse(i)?;
+    Ok((
+        i,
+        rest.into_iter().fold(init, |acc, (op, e)| {
+            Expr::BinOp(Box::new(acc), op, Box::new(e))
+        }),
+    ))
+}
+
+/// A single lexical element of a Nerdle expression or equation.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Token {
+    Digit(u8),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Square,
+    Cube,
+    Caret,
+    Bang,
+    LParen,
+    RParen,
+    Equals,
+}
+
+/// Split the provided string into tokens, without checking that they form a
+/// valid expression. Both `s`/`c` and the unicode glyphs are read as squares
+/// and cubes.
+pub fn tokenize(i: &str) -> Result<Vec<Token>, EvalError> {
+    i.chars()
+        .map(|c| {
+            Ok(match c {
+                '0'..='9' => Token::Digit(c as u8 - b'0'),
+                '+' => Token::Plus,
+                '-' => Token::Minus,
+                '*' => Token::Star,
+                '/' => Token::Slash,
+                '²' | 's' => Token::Square,
+                '³' | 'c' => Token::Cube,
+                '^' => Token::Caret,
+                '!' => Token::Bang,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                '=' => Token::Equals,
+                _ => return Err(EvalError::ParseFailed),
+            })
+        })
+        .collect()
+}
+
 #[test]
 fn test_evaluator() {
     assert_eq!(eval("(2)").unwrap(), 2);
@@ -220,5 +996,578 @@ fn test_evaluator() {
     assert_eq!(eval("2*2/(5-1)+3").unwrap(), 4);
     assert_eq!(eval("(5/4)*(4/5)").unwrap(), 1);
     assert_eq!(eval("0³/15+3²").unwrap(), 9);
-    assert!(eval("4/5").is_err());
+    assert_eq!(eval("4/5"), Err(EvalError::NonIntegerResult));
+}
+
+#[test]
+fn test_eval_errors() {
+    assert_eq!(eval("2*"), Err(EvalError::TrailingInput));
+    assert_eq!(eval("(2"), Err(EvalError::ParseFailed));
+    assert_eq!(eval("+2"), Err(EvalError::ParseFailed));
+    assert_eq!(eval("2)"), Err(EvalError::TrailingInput));
+    assert_eq!(eval("9999*9999*99"), Err(EvalError::Overflow));
+    assert_eq!(eval("99999*99999"), Err(EvalError::Overflow));
+    assert_eq!(eval("99999999999"), Err(EvalError::Overflow));
+    assert_eq!(eval("1/3+1/3"), Err(EvalError::NonIntegerResult));
+}
+
+#[test]
+fn test_eval_rational() {
+    assert_eq!(eval_rational("4/5").unwrap(), Rational32::new(4, 5));
+    assert_eq!(eval_rational("2/4").unwrap(), Rational32::new(1, 2));
+    assert_eq!(
+        eval_rational("(5/4)*(4/5)").unwrap(),
+        Rational32::from_integer(1)
+    );
+    assert_eq!(
+        eval_rational("2*(3+4)").unwrap(),
+        Rational32::from_integer(14)
+    );
+    assert_eq!(eval_rational("4/5)"), Err(EvalError::TrailingInput));
+    assert!(eval_rational("1/0").is_err());
+}
+
+#[test]
+fn test_eval_with() {
+    assert_eq!(eval_with::<i32>("2*(3+4)"), Ok(14));
+    assert_eq!(eval_with::<i32>("3/2"), Err(EvalError::NonIntegerResult));
+    assert_eq!(eval_with::<Rational32>("3/2"), Ok(Rational32::new(3, 2)));
+}
+
+#[test]
+fn test_caret_exponent() {
+    assert_eq!(eval("2^5").unwrap(), 32);
+    assert_eq!(eval("(1+1)^4").unwrap(), 16);
+    assert_eq!(eval("2^0").unwrap(), 1);
+    assert_eq!(eval("2^3*4").unwrap(), 32);
+    assert_eq!(eval("2^3^2").unwrap(), 64);
+    assert_eq!(eval("2^3²").unwrap(), 64);
+    assert_eq!(eval("2^9^9"), Err(EvalError::Overflow));
+    assert_eq!(eval("2^10"), Err(EvalError::TrailingInput));
+    assert_eq!(eval("2^(3)"), Err(EvalError::TrailingInput));
+}
+
+#[test]
+fn test_intermediate_overflow() {
+    // The intermediate product overflows `i32`, but the result doesn't
+    assert_eq!(eval("99999*99999/99999").unwrap(), 99999);
+    assert_eq!(eval("9999c-9999c+1").unwrap(), 1);
+    assert_eq!(eval("9999c/9999/9999").unwrap(), 9999);
+    assert_eq!(
+        eval_with::<i32>("9999c/9999/9999"),
+        Err(EvalError::Overflow)
+    );
+}
+
+#[test]
+fn test_i64_overflow() {
+    // 9^9³ is 9^27, and 3^9^6 is 3^54, which is the same; neither fits in `i64`
+    let e = "9^9³/3^9^6";
+    if cfg!(feature = "bigint") {
+        assert_eq!(eval(e), Ok(1));
+        assert_eq!(eval_ast(&parse(e).unwrap()), Ok(1));
+        assert_eq!(eval("9^9³/3^9^6*4/2"), Ok(2));
+        assert_eq!(eval("9^9³/3^9^6+2147483647"), Err(EvalError::Overflow));
+        // Found by fuzzing: this would need billions of bytes
+        assert_eq!(
+            eval("((0-2)ccccccccccccccccccccc^9--2)"),
+            Err(EvalError::Overflow)
+        );
+    } else {
+        assert_eq!(eval(e), Err(EvalError::Overflow));
+        assert_eq!(eval_ast(&parse(e).unwrap()), Err(EvalError::Overflow));
+    }
+}
+
+#[test]
+fn test_min_divided_by_minus_one() {
+    // (-2)^63 is `i64::MIN`, and (-2)^31 is `i32::MIN`; dividing either by -1
+    // used to panic computing the remainder
+    let min_i64 = "(0-2)^9^7/(0-1)";
+    let min_i32 = "(0-2)^9^3*16/(0-1)";
+    assert_eq!(eval(min_i64), Err(EvalError::Overflow));
+    assert_eq!(eval_ast(&parse(min_i64).unwrap()), Err(EvalError::Overflow));
+    assert_eq!(eval_with::<i64>(min_i64), Err(EvalError::Overflow));
+    assert_eq!(eval_with::<i32>(min_i32), Err(EvalError::Overflow));
+    assert_eq!(eval_with::<i64>(min_i32), Ok(1 << 31));
+    assert_eq!(eval("(0-2)^9^3*16/(0-1)/2"), Ok(1 << 30));
+}
+
+#[test]
+fn test_division_by_zero() {
+    assert_eq!(eval("1/0"), Err(EvalError::DivisionByZero));
+    assert_eq!(eval("1/(2-2)"), Err(EvalError::DivisionByZero));
+    assert_eq!(eval("1/2/0"), Err(EvalError::DivisionByZero));
+    assert_eq!(eval_rational("1/0"), Err(EvalError::DivisionByZero));
+    assert_eq!(eval("0/1").unwrap(), 0);
+}
+
+#[test]
+fn test_leading_zeros() {
+    assert_eq!(eval("0").unwrap(), 0);
+    assert_eq!(eval("05"), Err(EvalError::ParseFailed));
+    assert_eq!(eval("007"), Err(EvalError::ParseFailed));
+    assert_eq!(eval("10").unwrap(), 10);
+    assert_eq!(eval("100").unwrap(), 100);
+    assert_eq!(eval("0+0").unwrap(), 0);
+    assert!(eval("10+05").is_err());
+}
+
+#[test]
+fn test_tokenize() {
+    assert_eq!(
+        tokenize("(1+2)s*3c=243").unwrap(),
+        vec![
+            Token::LParen,
+            Token::Digit(1),
+            Token::Plus,
+            Token::Digit(2),
+            Token::RParen,
+            Token::Square,
+            Token::Star,
+            Token::Digit(3),
+            Token::Cube,
+            Token::Equals,
+            Token::Digit(2),
+            Token::Digit(4),
+            Token::Digit(3),
+        ]
+    );
+    assert_eq!(tokenize("3²-2³").unwrap(), tokenize("3s-2c").unwrap());
+    assert_eq!(
+        tokenize("9/3^2").unwrap(),
+        vec![
+            Token::Digit(9),
+            Token::Slash,
+            Token::Digit(3),
+            Token::Caret,
+            Token::Digit(2),
+        ]
+    );
+    // Tokens need not form a valid expression
+    assert_eq!(tokenize(")+").unwrap(), vec![Token::RParen, Token::Plus]);
+    assert_eq!(tokenize(""), Ok(vec![]));
+    assert_eq!(tokenize("1 + 2"), Err(EvalError::ParseFailed));
+    assert_eq!(tokenize("1x2"), Err(EvalError::ParseFailed));
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(
+        parse("2*(3+4)s").unwrap(),
+        Expr::BinOp(
+            Box::new(Expr::Num(2)),
+            Op::Mul,
+            Box::new(Expr::Pow(
+                Box::new(Expr::Paren(Box::new(Expr::BinOp(
+                    Box::new(Expr::Num(3)),
+                    Op::Add,
+                    Box::new(Expr::Num(4)),
+                )))),
+                2
+            )),
+        )
+    );
+    assert_eq!(
+        parse("1-2-3").unwrap(),
+        Expr::BinOp(
+            Box::new(Expr::BinOp(
+                Box::new(Expr::Num(1)),
+                Op::Sub,
+                Box::new(Expr::Num(2))
+            )),
+            Op::Sub,
+            Box::new(Expr::Num(3)),
+        )
+    );
+    assert_eq!(parse("2*(3+4)s").unwrap().to_string(), "2*(3+4)²");
+    assert_eq!(parse("2^5-1c").unwrap().to_string(), "2^5-1³");
+    assert_eq!(parse("3!s").unwrap().to_string(), "3!²");
+    assert_eq!(parse("(2"), Err(EvalError::ParseFailed));
+    assert_eq!(parse("2)"), Err(EvalError::TrailingInput));
+    assert_eq!(parse("05"), Err(EvalError::ParseFailed));
+}
+
+#[test]
+fn test_eval_ast() {
+    for i in [
+        "(2)",
+        "2*(3+4)",
+        "2*2/(5-1)+3",
+        "(5/4)*(4/5)",
+        "0³/15+3²",
+        "4/5",
+        "1/(2-2)",
+        "99999*99999",
+        "99999*99999/99999",
+        "2^3^2",
+        "(2+1)!-3!",
+        "1/3*99999*99999/99999",
+    ] {
+        assert_eq!(eval_ast(&parse(i).unwrap()), eval(i), "{}", i);
+    }
+
+    // With two problems, each reports the one it finds first
+    assert_eq!(eval("6/0/21!"), Err(EvalError::Overflow));
+    assert_eq!(
+        eval_ast(&parse("6/0/21!").unwrap()),
+        Err(EvalError::DivisionByZero)
+    );
+}
+
+#[test]
+fn test_eval_ast_rational() {
+    for i in [
+        "2/3+1/3",
+        "4/5",
+        "(5/4)*(4/5)",
+        "1/(2-2)",
+        "(1/2)²*8",
+        "3!/4",
+    ] {
+        assert_eq!(
+            eval_ast_rational(&parse(i).unwrap()),
+            eval_rational(i),
+            "{}",
+            i
+        );
+    }
+
+    let steps = eval_ast_steps(&parse("2/3+1/3").unwrap()).unwrap();
+    let steps = steps
+        .iter()
+        .map(|(e, v)| (e.to_string(), v.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        steps,
+        [("2/3", "2/3"), ("1/3", "1/3"), ("2/3+1/3", "1")]
+            .map(|(e, v)| (e.to_string(), v.to_string()))
+    );
+
+    let steps = eval_ast_steps(&parse("(1+1/2)²").unwrap()).unwrap();
+    assert_eq!(
+        steps.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        [
+            Rational32::new(1, 2),
+            Rational32::new(3, 2),
+            Rational32::new(9, 4)
+        ]
+    );
+    assert_eq!(steps.last().unwrap().0.to_string(), "(1+1/2)²");
+    assert!(eval_ast_steps(&parse("1/(2-2)").unwrap()).is_err());
+}
+
+#[test]
+fn test_precedence() {
+    for (i, v) in [
+        ("2²*3", 12),
+        ("3*2²", 12),
+        ("2³/4", 2),
+        ("16/2³", 2),
+        ("(1+1)²", 4),
+        ("2s+3", 7),
+        ("2+3s", 11),
+        ("2+3²", 11),
+        ("(2+3)²", 25),
+        ("10-2²", 6),
+        ("2^3*2", 16),
+        ("2*2^3", 16),
+        ("2²³", 64),
+        ("2^3^2", 64),
+        ("3!²", 36),
+        ("2²!", 24),
+        ("2+3!", 8),
+        ("2+3*4", 14),
+        ("2*3+4", 10),
+        ("12/2/3", 2),
+        ("2*6/3", 4),
+        ("8-3-2", 3),
+        ("8-3+2", 7),
+        ("8/(2*2)", 2),
+    ] {
+        assert_eq!(eval(i), Ok(v), "{}", i);
+        assert_eq!(eval_ast(&parse(i).unwrap()), Ok(v), "{}", i);
+    }
+
+    // 6/2² is 6/4, not 3²
+    assert_eq!(eval_rational("6/2²"), Ok(Rational32::new(3, 2)));
+    assert_eq!(eval("6/2²"), Err(EvalError::NonIntegerResult));
+
+    let num = |n| Box::new(Expr::Num(n));
+    assert_eq!(
+        parse("2+3s"),
+        Ok(Expr::BinOp(num(2), Op::Add, Box::new(Expr::Pow(num(3), 2))))
+    );
+    assert_eq!(
+        parse("6/2²"),
+        Ok(Expr::BinOp(num(6), Op::Div, Box::new(Expr::Pow(num(2), 2))))
+    );
+    assert_eq!(
+        parse("12/2/3"),
+        Ok(Expr::BinOp(
+            Box::new(Expr::BinOp(num(12), Op::Div, num(2))),
+            Op::Div,
+            num(3)
+        ))
+    );
+
+    // There's no unary minus, so a postfix operator can't apply to one
+    assert_eq!(eval("-2²"), Err(EvalError::ParseFailed));
+}
+
+#[test]
+fn test_validate_equation() {
+    assert_eq!(validate_equation("12+34=46"), Ok(true));
+    assert_eq!(validate_equation("3²+4²=5²"), Ok(true));
+    assert_eq!(validate_equation("1/2=2/4"), Ok(true));
+    assert_eq!(validate_equation("12+34=47"), Ok(false));
+    assert_eq!(validate_equation("1=2=3"), Err(EvalError::ParseFailed));
+    assert_eq!(validate_equation("12+34"), Err(EvalError::ParseFailed));
+    assert_eq!(validate_equation("12+=46"), Err(EvalError::TrailingInput));
+    assert_eq!(validate_equation("=46"), Err(EvalError::ParseFailed));
+    assert_eq!(validate_equation("1/0=1"), Err(EvalError::DivisionByZero));
+    // Intermediate values which overflow are retried with wider types
+    assert_eq!(validate_equation("50000*50000/50000=50000"), Ok(true));
+    assert_eq!(validate_equation("50000*50000/50000=49999"), Ok(false));
+    assert_eq!(validate_equation("99999*99999=1"), Ok(false));
+    assert_eq!(validate_equation("2^9^9=1"), Err(EvalError::Overflow));
+}
+
+#[test]
+fn test_validate_relation() {
+    assert_eq!(validate_relation("1+2<4"), Ok(true));
+    assert_eq!(validate_relation("1+2>4"), Ok(false));
+    assert_eq!(validate_relation("1+3<4"), Ok(false));
+    assert_eq!(validate_relation("2²>3"), Ok(true));
+    assert_eq!(validate_relation("1/3<1/2"), Ok(true));
+    assert_eq!(validate_relation("12+34=46"), Ok(true));
+    assert_eq!(validate_relation("12+34=47"), Ok(false));
+    assert_eq!(validate_relation("1<2<3"), Err(EvalError::ParseFailed));
+    assert_eq!(validate_relation("1<2=2"), Err(EvalError::ParseFailed));
+    assert_eq!(validate_relation("12+34"), Err(EvalError::ParseFailed));
+    assert_eq!(validate_relation("<4"), Err(EvalError::ParseFailed));
+    assert_eq!(validate_relation("1/0>1"), Err(EvalError::DivisionByZero));
+    assert_eq!(validate_relation("50000*50000/50000>49999"), Ok(true));
+}
+
+#[test]
+fn test_factorial() {
+    assert_eq!(eval("3!").unwrap(), 6);
+    assert_eq!(eval("0!").unwrap(), 1);
+    assert_eq!(eval("(2+1)!").unwrap(), 6);
+    assert_eq!(eval("3!+0").unwrap(), 6);
+    assert_eq!(eval("3!!").unwrap(), 720);
+    assert_eq!(eval("3!²").unwrap(), 36);
+    assert_eq!(eval("2*3!").unwrap(), 12);
+    assert_eq!(eval("12!/11!").unwrap(), 12);
+    assert_eq!(eval("13!"), Err(EvalError::Overflow));
+    assert_eq!(eval("(0-1)!"), Err(EvalError::InvalidFactorial));
+    assert_eq!(eval("(1/2)!"), Err(EvalError::InvalidFactorial));
+    assert_eq!(
+        eval_ast(&parse("(0-1)!").unwrap()),
+        Err(EvalError::InvalidFactorial)
+    );
+    assert_eq!(
+        eval_located("2+(0-1)!"),
+        Err((EvalError::InvalidFactorial, 7))
+    );
+    // Integers out of range of `i32` are still told apart by their sign
+    assert_eq!(eval("(0-99999*99999)!"), Err(EvalError::InvalidFactorial));
+    assert_eq!(eval("(99999*99999)!"), Err(EvalError::Overflow));
+    assert_eq!(eval_with::<i32>("12!").unwrap(), 479_001_600);
+    assert_eq!(tokenize("3!").unwrap(), vec![Token::Digit(3), Token::Bang]);
+}
+
+#[test]
+fn test_unicode_powers() {
+    assert_eq!(eval("3²").unwrap(), 9);
+    assert_eq!(eval("2³").unwrap(), 8);
+    assert_eq!(eval("3²").unwrap(), eval("3s").unwrap());
+    assert_eq!(eval("(1+2)³-2²").unwrap(), 23);
+    assert_eq!(eval("2²³").unwrap(), 64);
+
+    for eq in ["3²=9", "2³+1=9", "10-2³=2"] {
+        let (lhs, rhs) = eq.split_once('=').unwrap();
+        assert_eq!(eval(lhs).unwrap(), rhs.parse::<i32>().unwrap(), "{}", eq);
+    }
+}
+
+#[test]
+fn test_eval_config() {
+    let no_div = EvalConfig {
+        allow_div: false,
+        ..Default::default()
+    };
+    assert_eq!(eval_config("6/2", &no_div), Err(EvalError::ParseFailed));
+    assert_eq!(eval_config("6*2", &no_div), Ok(12));
+    assert_eq!(eval_config("6/2", &EvalConfig::default()), Ok(3));
+
+    let no_cube_or_paren = EvalConfig {
+        allow_cube: false,
+        allow_paren: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        eval_config("2c", &no_cube_or_paren),
+        Err(EvalError::ParseFailed)
+    );
+    assert_eq!(
+        eval_config("2³", &no_cube_or_paren),
+        Err(EvalError::ParseFailed)
+    );
+    assert_eq!(
+        eval_config("(2)", &no_cube_or_paren),
+        Err(EvalError::ParseFailed)
+    );
+    assert_eq!(eval_config("2s+1", &no_cube_or_paren), Ok(5));
+}
+
+#[test]
+fn test_rational64_fallback() {
+    // The numerator overflows `Rational32` after the non-integer division
+    assert_eq!(
+        eval_rational("1/3*99999*99999/99999"),
+        Err(EvalError::Overflow)
+    );
+    assert_eq!(eval("1/3*99999*99999/99999").unwrap(), 33333);
+    assert_eq!(eval("4/7*999c*7/999s").unwrap(), 3996);
+    assert_eq!(eval("4/7*999c"), Err(EvalError::NonIntegerResult));
+    assert_eq!(eval("1/3*99999*99999"), Err(EvalError::Overflow));
+
+    // A 10-slot row which `Rational32` couldn't evaluate, since 98^9 only fits
+    // in 64 bits, and it isn't divisible by 3
+    assert_eq!(eval_rational("98^9/3*0"), Err(EvalError::Overflow));
+    assert_eq!(eval("98^9/3*0"), Ok(0));
+    assert_eq!(validate_equation("98^9/3*0=0"), Ok(true));
+}
+
+#[test]
+fn test_eval_located() {
+    assert_eq!(eval_located("12+34"), Ok(46));
+    assert_eq!(eval_located("12+x"), Err((EvalError::TrailingInput, 2)));
+    assert_eq!(eval_located("1+1/0"), Err((EvalError::DivisionByZero, 3)));
+    assert_eq!(eval_located("1/2+1/0"), Err((EvalError::DivisionByZero, 5)));
+    assert_eq!(eval_located("(2"), Err((EvalError::ParseFailed, 2)));
+    assert_eq!(eval_located("x"), Err((EvalError::ParseFailed, 0)));
+    assert_eq!(eval_located("3²+x"), Err((EvalError::TrailingInput, 3)));
+    assert_eq!(eval_located("4/5"), Err((EvalError::NonIntegerResult, 0)));
+    // Still too large for an `i32` with `Rational64`, so this keeps the offset
+    // of the `*` chain where `Rational32` overflowed
+    assert_eq!(
+        eval_located("2+1/3*99999*99999"),
+        Err((EvalError::Overflow, 3))
+    );
+}
+
+#[test]
+fn test_eval_strict() {
+    assert_eq!(eval_strict("12+3"), Ok(15));
+    assert_eq!(eval_strict("1 2+3"), Err(EvalError::ParseFailed));
+    assert_eq!(eval_strict(" 12+3"), Err(EvalError::ParseFailed));
+    assert_eq!(eval_strict("12+3\n"), Err(EvalError::ParseFailed));
+    assert_eq!(eval_strict("12+\t3"), Err(EvalError::ParseFailed));
+    assert_eq!(eval_strict("4/5"), Err(EvalError::NonIntegerResult));
+    assert!(eval("1 2+3").is_err());
+}
+
+#[test]
+fn test_decimal_width() {
+    for v in [0, 9, 10, 99, 100, -1, -10, i32::MAX, i32::MIN] {
+        assert_eq!(decimal_width(v), v.to_string().len(), "{}", v);
+    }
+    assert_eq!(decimal_width(0), 1);
+    assert_eq!(decimal_width(9), 1);
+    assert_eq!(decimal_width(10), 2);
+    assert_eq!(decimal_width(99), 2);
+    assert_eq!(decimal_width(100), 3);
+    assert_eq!(decimal_width(-5), 2);
+    assert_eq!(decimal_width(-100), 4);
+}
+
+#[cfg(test)]
+thread_local! {
+    static POW_CALLS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+// An `i32` which counts the number of `pow` calls made on it
+#[cfg(test)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct CountingPow(i32);
+
+#[cfg(test)]
+impl Val for CountingPow {
+    fn add(self, other: Self) -> Result<Self, ComputeError> {
+        Val::add(self.0, other.0).map(CountingPow)
+    }
+    fn sub(self, other: Self) -> Result<Self, ComputeError> {
+        Val::sub(self.0, other.0).map(CountingPow)
+    }
+    fn mul(self, other: Self) -> Result<Self, ComputeError> {
+        Val::mul(self.0, other.0).map(CountingPow)
+    }
+    fn div(self, other: Self) -> Result<Self, ComputeError> {
+        Val::div(self.0, other.0).map(CountingPow)
+    }
+    fn pow(self, pow: usize) -> Result<Self, ComputeError> {
+        POW_CALLS.with(|c| c.set(c.get() + 1));
+        Val::pow(self.0, pow).map(CountingPow)
+    }
+    fn to_integer(self) -> Option<i32> {
+        Some(self.0)
+    }
+    fn from_integer(i: i32) -> Self {
+        CountingPow(i)
+    }
+    fn is_natural(&self) -> bool {
+        self.0.is_natural()
+    }
+}
+
+#[test]
+fn test_chained_powers() {
+    assert_eq!(eval("3ss").unwrap(), 81);
+    assert_eq!(eval("2sc").unwrap(), 64);
+    assert_eq!(eval("2²³").unwrap(), 64);
+    assert_eq!(eval("2cs").unwrap(), 64);
+    assert_eq!(eval("9ss").unwrap(), 6561);
+    assert_eq!(eval("(2s)c").unwrap(), eval("2sc").unwrap());
+    assert_eq!(eval("9ccc"), Err(EvalError::Overflow));
+    assert_eq!(eval("99cc"), Err(EvalError::Overflow));
+
+    // 99³ fits in an `i32` but (99³)³ doesn't, so the trailing squares are
+    // never computed
+    POW_CALLS.with(|c| c.set(0));
+    assert_eq!(eval_with::<CountingPow>("99ccss"), Err(EvalError::Overflow));
+    assert_eq!(POW_CALLS.with(|c| c.get()), 2);
+}
+
+#[test]
+fn test_evaluate() {
+    assert_eq!(
+        evaluate("2*(3+4)"),
+        Ok(Evaluation {
+            integer: Some(14),
+            rational: Rational32::from_integer(14),
+            overflowed_i32: false,
+        })
+    );
+    assert_eq!(
+        evaluate("4/5"),
+        Ok(Evaluation {
+            integer: None,
+            rational: Rational32::new(4, 5),
+            overflowed_i32: false,
+        })
+    );
+    assert_eq!(
+        evaluate("99999*99999/99999"),
+        Ok(Evaluation {
+            integer: Some(99999),
+            rational: Rational32::from_integer(99999),
+            overflowed_i32: true,
+        })
+    );
+    assert_eq!(evaluate("1/0"), Err(EvalError::DivisionByZero));
+    assert_eq!(evaluate("99999*99999"), Err(EvalError::Overflow));
+    assert_eq!(evaluate("(2"), Err(EvalError::ParseFailed));
 }
@@ -3,9 +3,19 @@
 //! Based partially on Digital Trauma's recursive generator approach
 //! https://codegolf.stackexchange.com/a/258767
 
-use std::io::Write;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::ops::{ControlFlow, Range};
+use std::path::Path;
 
-use crate::eval::eval;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use rayon::prelude::*;
+
+use crate::eval::{decimal_width, eval, validate_equation};
+use crate::mask::{matches_mask, normalize_power, Masks};
 
 /// Call `visitor` on all valid Nerdle equations which take exactly `slots`
 /// slots.
@@ -14,198 +24,823 @@ use crate::eval::eval;
 /// symbols, respectively.
 ///
 /// if `extended` is true, generates with parentheses, squares, and cubes
+///
+/// The order is deterministic, but not sorted: the `=` is placed after all the
+/// digits of the left-hand side, so `111/3=37` comes before `111/37=3` even
+/// though `=` sorts after `7`. Use `gen_sorted` for a sorted corpus.
 pub fn gen(slots: usize, visitor: &mut dyn FnMut(&str), extended: bool) {
-    let mut buf = vec![0; slots];
-    gen_nz_digit(0, 0, &mut buf, visitor, extended);
-    if extended {
-        gen_open(0, 0, &mut buf, visitor);
-    }
+    gen_with_options(
+        slots,
+        GenOptions {
+            features: GenFeatures::extended(extended),
+            ..Default::default()
+        },
+        visitor,
+    );
 }
 
-///  Helper function for a visitor that writes the output to the provided file,
-///  keeping the count in `ct`.
-pub fn line_writer<'a>(f: &'a mut impl Write, ct: &'a mut usize) -> impl FnMut(&str) + 'a {
-    move |s| {
-        for c in s.chars() {
-            write!(
-                f,
-                "{}",
-                match c {
-                    's' => '²',
-                    'c' => '³',
-                    c => c,
-                }
-            )
-            .unwrap();
+/// Which symbols, beyond digits and the four operators, may be generated.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct GenFeatures {
+    /// Parentheses
+    pub paren: bool,
+    /// Squares, written as `s`
+    pub square: bool,
+    /// Cubes, written as `c`
+    pub cube: bool,
+}
+
+impl GenFeatures {
+    /// All of the features if `extended` is true, as in maxi Nerdle, or none
+    /// of them otherwise, as in classic Nerdle.
+    pub fn extended(extended: bool) -> Self {
+        GenFeatures {
+            paren: extended,
+            square: extended,
+            cube: extended,
         }
-        writeln!(f).unwrap();
-        if *ct % 10000 == 0 {
-            eprintln!("{}: {}", ct, s);
+    }
+}
+
+/// Like `gen`, but also passes the value of each equation to `visitor`, which
+/// saves re-parsing it when that's what the caller needs.
+pub fn gen_valued(slots: usize, extended: bool, visitor: &mut dyn FnMut(&str, i32)) {
+    gen_with_options_valued(
+        slots,
+        GenOptions {
+            features: GenFeatures::extended(extended),
+            ..Default::default()
+        },
+        visitor,
+    );
+}
+
+/// Options controlling which equations `gen_with_options` generates.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct GenOptions {
+    /// Which symbols to generate besides digits and operators
+    pub features: GenFeatures,
+    /// Only generate one ordering of the operands of a top-level `+` or `*`.
+    ///
+    /// This is deliberately conservative: it only applies when the left-hand
+    /// side has exactly one operator outside of parentheses, and that operator
+    /// is `+` or `*`. Then `a+b=v` is kept only if `a <= b`, comparing the
+    /// operands as strings, so `12+34=46` is kept and `34+12=46` is dropped.
+    /// Equations like `1+2+3=6` or `2*3+4=10` are never collapsed. With several
+    /// `=` signs, only the first expression is considered.
+    pub canonical: bool,
+    /// The number of `=` signs in each equation, which must be at least 1. If
+    /// there are several, every expression between them must have the same
+    /// value as the number on the far right, e.g. `1+2=6/2=3`.
+    pub equals_count: usize,
+    /// Also generate equations with a negative result, e.g. `1-5=-4`. Nerdle
+    /// doesn't have negative-number solutions, so this is off by default.
+    pub allow_negative: bool,
+    /// Skip equations which are trivial identities. An equation is excluded if,
+    /// outside of parentheses, it has
+    /// - `1` as either operand of a `*`, e.g. `5*1=5` or `1*5+2=7`
+    /// - `1` as the right operand of a `/`, e.g. `5/1=5`
+    /// - `0` as either operand of a `+` or the right operand of a `-`
+    ///
+    /// Only literal operands count, so `5*1²=5` and `5*(1)=5` are kept. Lone
+    /// zeros are never generated as operands, so in practice only the first
+    /// two patterns occur.
+    pub exclude_trivial: bool,
+    /// The deepest parentheses may be nested, so 1 allows `(1+2)*3=9` but not
+    /// `((1+2))*3=9`. Unlimited by default; this has no effect unless
+    /// `features.paren` is set.
+    pub max_depth: usize,
+    /// Which digits may appear anywhere in the equation, including its value,
+    /// indexed by digit. All of them are allowed by default.
+    pub allowed_digits: [bool; 10],
+}
+
+impl Default for GenOptions {
+    fn default() -> Self {
+        GenOptions {
+            features: GenFeatures::default(),
+            canonical: false,
+            equals_count: 1,
+            allow_negative: false,
+            exclude_trivial: false,
+            max_depth: usize::MAX,
+            allowed_digits: [true; 10],
         }
-        *ct += 1;
     }
 }
 
-/// Try to insert a nonzero digit at `index`, and then recurse
-fn gen_nz_digit(
-    index: usize,
-    depth: usize,
-    buf: &mut [u8],
-    visitor: &mut dyn FnMut(&str),
-    extended: bool,
+/// Like `gen`, but with more control over which equations are generated.
+pub fn gen_with_options(slots: usize, options: GenOptions, visitor: &mut dyn FnMut(&str)) {
+    gen_with_options_valued(slots, options, &mut |s, _| visitor(s));
+}
+
+/// Like `gen_with_options`, but also passes the value of each equation to
+/// `visitor`, so it doesn't need to be parsed back out of the string.
+pub fn gen_with_options_valued(
+    slots: usize,
+    options: GenOptions,
+    visitor: &mut dyn FnMut(&str, i32),
 ) {
-    if index >= buf.len() - 2 {
-        return;
-    }
+    assert!(options.equals_count >= 1, "equations need an `=` sign");
+    let mut accept = |buf: &mut [u8], index, v| {
+        if options.canonical && !is_canonical(&buf[..index]) {
+            return ControlFlow::Continue(());
+        }
+        if options.exclude_trivial && is_trivial(&buf[..index]) {
+            return ControlFlow::Continue(());
+        }
+        if let Some(s) = complete(buf, index, v) {
+            visitor(s, v);
+        }
+        ControlFlow::Continue(())
+    };
+    let mut g = Generator::new(slots, &mut accept);
+    g.extra_equals = options.equals_count - 1;
+    g.allow_negative = options.allow_negative;
+    g.max_depth = options.max_depth;
+    g.allowed_digits = options.allowed_digits;
+    g.run(options.features);
+}
+
+/// Collect all the equations generated by `gen` into memory, using the unicode
+/// ² and ³ glyphs.
+///
+/// This is convenient for the micro and classic variants, but note that the
+/// maxi corpus has over 2 million equations, which takes a lot of memory; prefer
+/// `gen` with a `line_writer` for that.
+pub fn collect_equations(slots: usize, extended: bool) -> Vec<String> {
+    let mut equations = vec![];
+    gen(
+        slots,
+        &mut |s| equations.push(s.chars().map(normalize_power).collect()),
+        extended,
+    );
+    equations
+}
+
+/// Like `collect_equations`, but sorted in byte order, e.g. so that the
+/// corpus can be binary-searched.
+pub fn gen_sorted(slots: usize, extended: bool) -> Vec<String> {
+    let mut equations = collect_equations(slots, extended);
+    equations.par_sort_unstable();
+    equations
+}
 
-    for i in 1..10 {
-        buf[index] = char::from_digit(i, 10).unwrap() as u8;
-        try_gen_eq(index + 1, depth, buf, visitor);
-        gen_digit(index + 1, depth, 1, buf, visitor, extended);
-        gen_oper(index + 1, depth, buf, visitor, extended);
-        if extended {
-            gen_squared(index + 1, depth, buf, visitor);
-            gen_cubed(index + 1, depth, buf, visitor);
-            if depth > 0 {
-                gen_close(index + 1, depth, buf, visitor);
+/// Collect all the equations generated by `gen` into memory, grouped by their
+/// value. Like `collect_equations`, this uses the unicode ² and ³ glyphs.
+pub fn group_by_value(slots: usize, extended: bool) -> BTreeMap<i32, Vec<String>> {
+    let mut groups: BTreeMap<i32, Vec<String>> = BTreeMap::new();
+    gen_valued(slots, extended, &mut |s, v| {
+        groups
+            .entry(v)
+            .or_default()
+            .push(s.chars().map(normalize_power).collect())
+    });
+    groups
+}
+
+/// Like `gen`, but only calls `visitor` on equations whose value is `target`.
+pub fn gen_for_target(slots: usize, extended: bool, target: i32, visitor: &mut dyn FnMut(&str)) {
+    Generator::new(slots, &mut |buf, index, v| {
+        if v != target {
+            return ControlFlow::Continue(());
+        }
+        if let Some(s) = complete(buf, index, v) {
+            visitor(s);
+        }
+        ControlFlow::Continue(())
+    })
+    .run(GenFeatures::extended(extended));
+}
+
+/// Count the valid Nerdle equations which take exactly `slots` slots, as
+/// generated by `gen`. This is faster than counting the equations passed to a
+/// visitor, since the equations are never written out.
+pub fn count_equations(slots: usize, extended: bool) -> usize {
+    let mut ct = 0;
+    Generator::new(slots, &mut |buf, index, v| {
+        if fits(buf, index, v) {
+            ct += 1;
+        }
+        ControlFlow::Continue(())
+    })
+    .run(GenFeatures::extended(extended));
+    ct
+}
+
+// The checked results of `count_equations`, as (slots, extended, count)
+const EXPECTED_COUNTS: &[(usize, bool, usize)] = &[
+    (5, false, 127),
+    (5, true, 144),
+    (6, false, 206),
+    (6, true, 409),
+    (7, false, 6_661),
+    (7, true, 7_908),
+    (8, false, 17_723),
+    (8, true, 29_139),
+    (9, false, 287_288),
+    (9, true, 370_668),
+    (10, false, 1_404_041),
+    (10, true, 2_177_736),
+];
+
+/// The number of equations `gen` is known to generate for `slots` and
+/// `extended`, to sanity-check a run against, or `None` if it hasn't been
+/// recorded. Micro nerdle is 5 slots, classic is 8, and maxi is 10 extended.
+///
+/// | slots | standard  | extended  |
+/// |-------|-----------|-----------|
+/// | 5     | 127       | 144       |
+/// | 6     | 206       | 409       |
+/// | 7     | 6,661     | 7,908     |
+/// | 8     | 17,723    | 29,139    |
+/// | 9     | 287,288   | 370,668   |
+/// | 10    | 1,404,041 | 2,177,736 |
+pub fn expected_count(slots: usize, extended: bool) -> Option<usize> {
+    EXPECTED_COUNTS
+        .iter()
+        .find(|&&(s, e, _)| s == slots && e == extended)
+        .map(|&(_, _, ct)| ct)
+}
+
+/// Like `gen`, but only calls `visitor` on equations which match `mask`.
+///
+/// Positions which are known to be green, purple, or black are pruned while the
+/// equation is being built, rather than filtering the full output afterwards.
+pub fn gen_with_mask(slots: usize, extended: bool, mask: &Masks, visitor: &mut dyn FnMut(&str)) {
+    let mut accept = |buf: &mut [u8], index, v| {
+        if let Some(s) = complete(buf, index, v) {
+            let normalized = s.chars().map(normalize_power).collect::<String>();
+            if matches_mask(&normalized, mask) {
+                visitor(s);
             }
         }
-    }
+        ControlFlow::Continue(())
+    };
+    let mut g = Generator::new(slots, &mut accept);
+    g.mask = Some(mask);
+    g.run(GenFeatures::extended(extended));
 }
 
-/// Try to insert a digit at `index`, and then recurse
-fn gen_digit(
-    index: usize,
-    depth: usize,
-    ndigits: usize,
-    buf: &mut [u8],
-    visitor: &mut dyn FnMut(&str),
+/// Like `gen`, but only calls `visitor` on equations shaped like `template`,
+/// such as `_*_+_=__`. Each `_` is a digit, and every other char (including
+/// any digits) is fixed in place. The equations follow the same rules as
+/// `gen`, so `_+_=_` doesn't match `0+1=1`, which has a lone zero operand.
+pub fn gen_template(template: &str, visitor: &mut dyn FnMut(&str)) {
+    let extended = template.contains(['(', ')', 's', 'c', '²', '³']);
+    let mask = Masks::from_template(template);
+    gen_with_mask(template.chars().count(), extended, &mask, visitor);
+}
+
+/// Like `gen`, but splits the search across threads by the first character of
+/// the equation. Each equation is passed to `visitor` exactly once, in no
+/// particular order.
+pub fn gen_par(slots: usize, extended: bool, visitor: impl Fn(&str) + Sync) {
+    // Digit 0 stands in for an opening parenthesis, since equations can't start
+    // with a zero
+    (0..10).into_par_iter().for_each(|first| {
+        let mut accept = |buf: &mut [u8], index, v| {
+            if let Some(s) = complete(buf, index, v) {
+                visitor(s);
+            }
+            ControlFlow::Continue(())
+        };
+        Generator::new(slots, &mut accept).run_first(first, GenFeatures::extended(extended));
+    });
+}
+
+/// Like `gen`, but only generates equations whose first character is in
+/// `first_digit_range`, where 0 stands in for an opening parenthesis. Running
+/// over disjoint ranges which cover `0..10` generates the same equations as
+/// `gen`, which makes it easy to shard or resume a long generation.
+pub fn gen_range(
+    slots: usize,
     extended: bool,
+    first_digit_range: Range<u32>,
+    visitor: &mut dyn FnMut(&str),
 ) {
-    if index >= buf.len() - 2 {
-        return;
+    let mut accept = |buf: &mut [u8], index, v| {
+        if let Some(s) = complete(buf, index, v) {
+            visitor(s);
+        }
+        ControlFlow::Continue(())
+    };
+    let mut g = Generator::new(slots, &mut accept);
+    for first in first_digit_range.filter(|d| *d < 10) {
+        g.run_first(first, GenFeatures::extended(extended));
     }
+}
 
-    // There's no point generating numbers longer than half the available LHS,
-    // since the resulting value won't fit on the RHS
-    if ndigits >= (buf.len() - 2) / 2 {
-        return;
-    }
-    for i in (1..10).chain(std::iter::once(0)) {
-        buf[index] = char::from_digit(i, 10).unwrap() as u8;
-        try_gen_eq(index + 1, depth, buf, visitor);
-        gen_digit(index + 1, depth, ndigits + 1, buf, visitor, extended);
-        gen_oper(index + 1, depth, buf, visitor, extended);
-        if extended {
-            gen_squared(index + 1, depth, buf, visitor);
-            gen_cubed(index + 1, depth, buf, visitor);
-            if depth > 0 {
-                gen_close(index + 1, depth, buf, visitor);
+/// Like `gen`, but returns the equations as an iterator. The equations are
+/// generated on a background thread, which stops early if the iterator is
+/// dropped.
+pub fn iter_equations(slots: usize, extended: bool) -> impl Iterator<Item = String> {
+    let (tx, rx) = std::sync::mpsc::sync_channel(1024);
+    std::thread::spawn(move || {
+        Generator::new(slots, &mut |buf, index, v| match complete(buf, index, v) {
+            // The receiver is gone, so stop generating
+            Some(s) if tx.send(s.to_string()).is_err() => ControlFlow::Break(()),
+            _ => ControlFlow::Continue(()),
+        })
+        .run(GenFeatures::extended(extended));
+    });
+    rx.into_iter()
+}
+
+/// How squares and cubes are written out by `styled_line_writer`.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum GlyphStyle {
+    /// The unicode ² and ³ glyphs
+    #[default]
+    Unicode,
+    /// `s` and `c`, as passed to the visitor
+    Ascii,
+    /// `^2` and `^3`
+    Caret,
+}
+
+impl GlyphStyle {
+    /// Write the generated equation `s` to `f` in this style
+    pub fn write(self, f: &mut impl Write, s: &str) -> std::io::Result<()> {
+        for c in s.chars() {
+            match (self, c) {
+                (GlyphStyle::Unicode, 's') => write!(f, "²")?,
+                (GlyphStyle::Unicode, 'c') => write!(f, "³")?,
+                (GlyphStyle::Caret, 's') => write!(f, "^2")?,
+                (GlyphStyle::Caret, 'c') => write!(f, "^3")?,
+                (_, c) => write!(f, "{}", c)?,
             }
         }
+        Ok(())
     }
 }
 
-/// Try to insert an operator at `index`, and then recurse
-fn gen_oper(
-    index: usize,
-    depth: usize,
-    buf: &mut [u8],
-    visitor: &mut dyn FnMut(&str),
-    extended: bool,
-) {
-    if index > buf.len() - 3 {
-        return;
+/// A file to write generated equations to, which is gzip-compressed if its
+/// name ends in `.gz`.
+pub enum OutputFile {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl OutputFile {
+    /// Create the file at `path`, compressing it if the extension is `.gz`
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let f = BufWriter::new(File::create(path)?);
+        if path.extension().is_some_and(|ext| ext == "gz") {
+            Ok(OutputFile::Gzip(GzEncoder::new(f, Compression::default())))
+        } else {
+            Ok(OutputFile::Plain(f))
+        }
     }
-    for op in [b'-', b'+', b'*', b'/'] {
-        buf[index] = op;
-        gen_nz_digit(index + 1, depth, buf, visitor, extended);
-        gen_open(index + 1, depth, buf, visitor);
+
+    /// Flush the file, writing out the gzip trailer if it's compressed. Errors
+    /// are ignored if the file is dropped without calling this.
+    pub fn finish(self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Plain(mut f) => f.flush(),
+            OutputFile::Gzip(f) => f.finish()?.flush(),
+        }
     }
 }
 
-/// Try to insert a square at `index`, and then recurse. Use `s` rather than the
-/// unicode square symbol so we use only one byte.
-fn gen_squared(index: usize, depth: usize, buf: &mut [u8], visitor: &mut dyn FnMut(&str)) {
-    if index > buf.len() - 2 {
-        return;
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFile::Plain(f) => f.write(buf),
+            OutputFile::Gzip(f) => f.write(buf),
+        }
     }
-    buf[index] = b's';
-    if index >= 3 {
-        try_gen_eq(index + 1, depth, buf, visitor);
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Plain(f) => f.flush(),
+            OutputFile::Gzip(f) => f.flush(),
+        }
+    }
+}
+
+/// Read a corpus with one equation per line, trimming each line and skipping
+/// blank ones. With `validate`, lines which `validate_equation` doesn't accept
+/// as true equations are dropped too, with a warning saying how many.
+pub fn read_corpus(r: impl BufRead, validate: bool) -> std::io::Result<Vec<String>> {
+    let mut lines = vec![];
+    for line in r.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() {
+            lines.push(line.to_string());
+        }
     }
-    gen_oper(index + 1, depth, buf, visitor, true);
-    if depth > 0 {
-        gen_close(index + 1, depth, buf, visitor);
+    if validate {
+        let total = lines.len();
+        lines = lines
+            .into_par_iter()
+            .filter(|l| validate_equation(l) == Ok(true))
+            .collect();
+        if lines.len() < total {
+            warn!(
+                "Dropped {} of {} lines which aren't valid equations",
+                total - lines.len(),
+                total
+            );
+        }
     }
+    Ok(lines)
+}
+
+/// Read and validate the corpus at `path`, see `read_corpus`
+pub fn load_corpus(path: &Path) -> std::io::Result<Vec<String>> {
+    read_corpus(BufReader::new(File::open(path)?), true)
 }
 
-/// Try to insert a cube at `index`, and then recurse. Use `s` rather than the
-/// unicode cube symbol so we use only one byte.
-fn gen_cubed(index: usize, depth: usize, buf: &mut [u8], visitor: &mut dyn FnMut(&str)) {
-    if index > buf.len() - 2 {
-        return;
+///  Helper function for a visitor that writes the output to the provided file,
+///  keeping the count in `ct`.
+pub fn line_writer<'a>(f: &'a mut impl Write, ct: &'a mut usize) -> impl FnMut(&str) + 'a {
+    styled_line_writer(f, ct, GlyphStyle::Unicode, None)
+}
+
+/// Like `line_writer`, but writes squares and cubes in the given `style`.
+///
+/// If provided, `progress` is called with the running count after every line,
+/// so callers can report progress at whatever cadence they like.
+pub fn styled_line_writer<'a>(
+    f: &'a mut impl Write,
+    ct: &'a mut usize,
+    style: GlyphStyle,
+    mut progress: Option<&'a mut dyn FnMut(usize)>,
+) -> impl FnMut(&str) + 'a {
+    move |s| {
+        style.write(f, s).unwrap();
+        writeln!(f).unwrap();
+        *ct += 1;
+        if let Some(progress) = progress.as_mut() {
+            progress(*ct);
+        }
     }
-    buf[index] = b'c';
-    if index >= 2 {
-        try_gen_eq(index + 1, depth, buf, visitor);
+}
+
+/// Whether the left-hand side `expr` should be kept when generating only
+/// canonical equations. See `GenOptions::canonical`.
+fn is_canonical(expr: &[u8]) -> bool {
+    let expr = expr.split(|b| *b == b'=').next().unwrap();
+    let mut depth = 0;
+    let mut split = None;
+    for (idx, b) in expr.iter().enumerate() {
+        match b {
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            b'+' | b'-' | b'*' | b'/' if depth == 0 => {
+                if split.is_some() {
+                    return true;
+                }
+                split = Some(idx);
+            }
+            _ => (),
+        }
     }
-    gen_oper(index + 1, depth, buf, visitor, true);
-    if depth > 0 {
-        gen_close(index + 1, depth, buf, visitor);
+    let Some(idx) = split else {
+        return true;
+    };
+    if !matches!(expr[idx], b'+' | b'*') {
+        return true;
     }
+    expr[..idx] <= expr[idx + 1..]
 }
 
-/// Try to insert an open parentheses at `index`, and then recurse
-fn gen_open(index: usize, depth: usize, buf: &mut [u8], visitor: &mut dyn FnMut(&str)) {
-    if index > buf.len() - 3 {
-        return;
+/// Whether any expression in `expr` is a trivial identity. See
+/// `GenOptions::exclude_trivial`.
+fn is_trivial(expr: &[u8]) -> bool {
+    expr.split(|b| *b == b'=').any(|expr| {
+        let mut depth = 0;
+        let mut operands = vec![];
+        let mut ops = vec![];
+        let mut start = 0;
+        for (idx, b) in expr.iter().enumerate() {
+            match b {
+                b'(' => depth += 1,
+                b')' => depth -= 1,
+                b'+' | b'-' | b'*' | b'/' if depth == 0 => {
+                    operands.push(&expr[start..idx]);
+                    ops.push(*b);
+                    start = idx + 1;
+                }
+                _ => (),
+            }
+        }
+        operands.push(&expr[start..]);
+
+        ops.iter().enumerate().any(|(i, op)| {
+            let (lhs, rhs) = (operands[i], operands[i + 1]);
+            match op {
+                b'+' => lhs == b"0" || rhs == b"0",
+                b'-' => rhs == b"0",
+                b'*' => lhs == b"1" || rhs == b"1",
+                b'/' => rhs == b"1",
+                _ => unreachable!(),
+            }
+        })
+    })
+}
+
+/// Whether writing `=v` at `index` exactly fills `buf`, counting the sign of a
+/// negative `v`.
+fn fits(buf: &[u8], index: usize, v: i32) -> bool {
+    index + decimal_width(v) + 1 == buf.len()
+}
+
+/// Write `=v` at `index` and return the completed equation, if it fits.
+fn complete(buf: &mut [u8], index: usize, v: i32) -> Option<&str> {
+    if !fits(buf, index, v) {
+        return None;
     }
-    buf[index] = b'(';
-    gen_nz_digit(index + 1, depth + 1, buf, visitor, true);
-    gen_open(index + 1, depth + 1, buf, visitor);
+    buf[index] = b'=';
+    write!(&mut buf[index + 1..], "{}", v).unwrap();
+    std::str::from_utf8(buf).ok()
 }
 
-/// Try to insert a close parentheses at `index`, and then recurse
-fn gen_close(index: usize, depth: usize, buf: &mut [u8], visitor: &mut dyn FnMut(&str)) {
-    debug_assert!(depth > 0);
-    if index > buf.len() - 2 {
-        return;
+// The callback for each equation found by a `Generator`
+type Accept<'a> = dyn FnMut(&mut [u8], usize, i32) -> ControlFlow<()> + 'a;
+
+/// The state of a single recursive search over equations.
+struct Generator<'a> {
+    buf: Vec<u8>,
+    /// Called with the buffer, the index at which the `=` sign would go, and the
+    /// value of the expression before it. Returning `Break` stops the search.
+    accept: &'a mut Accept<'a>,
+    /// Whether `accept` has asked to stop
+    stopped: bool,
+    /// If set, only place chars which the mask allows at each position
+    mask: Option<&'a Masks>,
+    /// How many more `=` signs to place before the final one
+    extra_equals: usize,
+    /// The start index of the expression currently being built, and the value
+    /// it needs to have, if it follows an earlier `=`
+    segment: Option<(usize, i32)>,
+    /// Which symbols beyond digits and operators to generate
+    features: GenFeatures,
+    /// Whether equations may have a negative value
+    allow_negative: bool,
+    /// The deepest parentheses may be nested
+    max_depth: usize,
+    /// Which digits may be placed, or appear in the value
+    allowed_digits: [bool; 10],
+}
+
+impl<'a> Generator<'a> {
+    fn new(slots: usize, accept: &'a mut Accept<'a>) -> Self {
+        Generator {
+            buf: vec![0; slots],
+            accept,
+            stopped: false,
+            mask: None,
+            extra_equals: 0,
+            segment: None,
+            features: GenFeatures::default(),
+            allow_negative: false,
+            max_depth: usize::MAX,
+            allowed_digits: [true; 10],
+        }
+    }
+
+    /// Write `b` at `index`, unless the mask rules it out there. Every step of
+    /// the search places a char before recursing, so this also ends the search
+    /// once it's stopped.
+    fn place(&mut self, index: usize, b: u8) -> bool {
+        if self.stopped {
+            return false;
+        }
+        if let Some(mask) = self.mask {
+            if !mask.allows(index, normalize_power(b as char)) {
+                return false;
+            }
+        }
+        self.buf[index] = b;
+        true
+    }
+
+    /// Generate only the equations starting with the digit `first`, or with an
+    /// open parenthesis if `first` is 0.
+    fn run_first(&mut self, first: u32, features: GenFeatures) {
+        self.features = features;
+        if first > 0 {
+            if self.buf.len() > 2 {
+                self.gen_placed_digit(first, 0, 0, 1);
+            }
+        } else if features.paren {
+            self.gen_open(0, 0);
+        }
     }
-    buf[index] = b')';
-    try_gen_eq(index + 1, depth - 1, buf, visitor);
-    gen_oper(index + 1, depth - 1, buf, visitor, true);
-    gen_squared(index + 1, depth - 1, buf, visitor);
-    gen_cubed(index + 1, depth - 1, buf, visitor);
-    if depth - 1 > 0 {
-        gen_close(index + 1, depth - 1, buf, visitor);
+
+    fn run(&mut self, features: GenFeatures) {
+        self.features = features;
+        self.gen_nz_digit(0, 0);
+        if features.paren {
+            self.gen_open(0, 0);
+        }
     }
-}
 
-/// Try to insert an equals sign at `index`, and then compute the value and call
-/// `visitor` if it's the right size.
-fn try_gen_eq(index: usize, depth: usize, buf: &mut [u8], visitor: &mut dyn FnMut(&str)) {
-    if depth > 0 {
-        return;
+    /// Try to insert a nonzero digit at `index`, and then recurse
+    fn gen_nz_digit(&mut self, index: usize, depth: usize) {
+        if index >= self.buf.len() - 2 {
+            return;
+        }
+
+        for i in 1..10 {
+            self.gen_placed_digit(i, index, depth, 1);
+        }
     }
-    let expr = std::str::from_utf8(&buf[..index]).unwrap();
-    if let Ok(v) = eval(expr) {
-        if v < 0 {
+
+    /// Try to insert a digit at `index`, and then recurse
+    fn gen_digit(&mut self, index: usize, depth: usize, ndigits: usize) {
+        if index >= self.buf.len() - 2 {
+            return;
+        }
+
+        // There's no point generating numbers longer than half the available
+        // LHS, since the resulting value won't fit on the RHS.
+        //
+        // An n-digit number is at least 10^(n-1), so to bring the result down
+        // to a w-digit value it needs another operand of at least n-1 digits
+        // to subtract or divide by (a product has at most as many digits as
+        // its factors together). That takes at least n + 1 + (n-1) + 1 + w
+        // slots, so n <= (slots - 2) / 2. The exceptions are:
+        // - equations without an operator, like `123=123`, which aren't puzzles
+        // - cubes, which pack n digits into about n/3 + 1 slots. These can
+        //   break the bound from 12 slots (e.g. `103823-47³=0`), but not for
+        //   the micro, classic, or maxi sizes.
+        if ndigits >= (self.buf.len() - 2) / 2 {
+            return;
+        }
+        for i in (1..10).chain(std::iter::once(0)) {
+            self.gen_placed_digit(i, index, depth, ndigits + 1);
+        }
+    }
+
+    /// Insert `digit` at `index` as the `ndigits`th digit of a number, and then
+    /// recurse
+    fn gen_placed_digit(&mut self, digit: u32, index: usize, depth: usize, ndigits: usize) {
+        if !self.allowed_digits[digit as usize] {
+            return;
+        }
+        if !self.place(index, char::from_digit(digit, 10).unwrap() as u8) {
+            return;
+        }
+        self.try_gen_eq(index + 1, depth);
+        self.gen_digit(index + 1, depth, ndigits);
+        self.gen_oper(index + 1, depth);
+        self.gen_squared(index + 1, depth);
+        self.gen_cubed(index + 1, depth);
+        if depth > 0 {
+            self.gen_close(index + 1, depth);
+        }
+    }
+
+    /// Try to insert an operator at `index`, and then recurse
+    fn gen_oper(&mut self, index: usize, depth: usize) {
+        if index > self.buf.len() - 3 {
+            return;
+        }
+        for op in [b'-', b'+', b'*', b'/'] {
+            if !self.place(index, op) {
+                continue;
+            }
+            self.gen_nz_digit(index + 1, depth);
+            self.gen_open(index + 1, depth);
+        }
+    }
+
+    /// Try to insert a square at `index`, and then recurse. Use `s` rather than
+    /// the unicode square symbol so we use only one byte.
+    fn gen_squared(&mut self, index: usize, depth: usize) {
+        if !self.features.square || index > self.buf.len() - 2 {
+            return;
+        }
+        if !self.place(index, b's') {
+            return;
+        }
+        self.try_gen_eq(index + 1, depth);
+        self.gen_oper(index + 1, depth);
+        if depth > 0 {
+            self.gen_close(index + 1, depth);
+        }
+    }
+
+    /// Try to insert a cube at `index`, and then recurse. Use `s` rather than
+    /// the unicode cube symbol so we use only one byte.
+    fn gen_cubed(&mut self, index: usize, depth: usize) {
+        if !self.features.cube || index > self.buf.len() - 2 {
+            return;
+        }
+        if !self.place(index, b'c') {
+            return;
+        }
+        self.try_gen_eq(index + 1, depth);
+        self.gen_oper(index + 1, depth);
+        if depth > 0 {
+            self.gen_close(index + 1, depth);
+        }
+    }
+
+    /// Try to insert an open parentheses at `index`, and then recurse
+    fn gen_open(&mut self, index: usize, depth: usize) {
+        if !self.features.paren || depth >= self.max_depth || index > self.buf.len() - 3 {
+            return;
+        }
+        if !self.place(index, b'(') {
+            return;
+        }
+        self.gen_nz_digit(index + 1, depth + 1);
+        self.gen_open(index + 1, depth + 1);
+    }
+
+    /// Try to insert a close parentheses at `index`, and then recurse
+    fn gen_close(&mut self, index: usize, depth: usize) {
+        debug_assert!(depth > 0);
+        if index > self.buf.len() - 2 {
+            return;
+        }
+        if !self.place(index, b')') {
+            return;
+        }
+        self.try_gen_eq(index + 1, depth - 1);
+        self.gen_oper(index + 1, depth - 1);
+        self.gen_squared(index + 1, depth - 1);
+        self.gen_cubed(index + 1, depth - 1);
+        if depth - 1 > 0 {
+            self.gen_close(index + 1, depth - 1);
+        }
+    }
+
+    /// Try to insert an equals sign at `index`, and then compute the value and
+    /// pass it to `accept`.
+    fn try_gen_eq(&mut self, index: usize, depth: usize) {
+        if depth > 0 || self.stopped {
+            return;
+        }
+        let start = self.segment.map_or(0, |(start, _)| start);
+        let expr = std::str::from_utf8(&self.buf[start..index]).unwrap();
+        let Ok(v) = eval(expr) else {
+            return;
+        };
+        if v < 0 && !self.allow_negative {
             // Nerdle doesn't have negative-number solutions
             return;
         }
-        let v_len = if v == 0 { 1 } else { (v.ilog10() + 1) as usize };
-        if index + v_len + 1 == buf.len() {
-            buf[index] = b'=';
-            write!(&mut buf[index + 1..], "{}", v).unwrap();
-            visitor(std::str::from_utf8(buf).unwrap());
+        // The value is written out after the last `=`, so it can't use any
+        // disallowed digits either
+        if self.allowed_digits.contains(&false)
+            && v.unsigned_abs()
+                .to_string()
+                .bytes()
+                .any(|b| !self.allowed_digits[(b - b'0') as usize])
+        {
+            return;
+        }
+        if self.segment.is_some_and(|(_, value)| value != v) {
+            return;
+        }
+        if self.extra_equals > 0 {
+            self.gen_segment(index, v);
+        } else {
+            self.stopped = (self.accept)(&mut self.buf, index, v).is_break();
+        }
+    }
+
+    /// Try to insert an equals sign at `index`, followed by another expression
+    /// which must also have the value `v`, and then recurse
+    fn gen_segment(&mut self, index: usize, v: i32) {
+        // Leave room for at least `=x=v`
+        if index + 4 > self.buf.len() {
+            return;
+        }
+        if !self.place(index, b'=') {
+            return;
         }
+        let saved = (self.segment, self.extra_equals);
+        self.segment = Some((index + 1, v));
+        self.extra_equals -= 1;
+        self.gen_nz_digit(index + 1, 0);
+        self.gen_open(index + 1, 0);
+        (self.segment, self.extra_equals) = saved;
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::gen;
+    use std::collections::HashSet;
+    use std::ops::ControlFlow;
+    use std::sync::Mutex;
+
+    use crate::eval::{eval, eval_ast, parse, validate_equation};
+    use crate::mask::{matches_mask, normalize_power, parse_mask_results, score};
+
+    use super::{
+        collect_equations, count_equations, expected_count, gen, gen_for_target, gen_par,
+        gen_range, gen_sorted, gen_template, gen_valued, gen_with_mask, gen_with_options,
+        group_by_value, is_trivial, iter_equations, line_writer, load_corpus, read_corpus,
+        styled_line_writer, GenFeatures, GenOptions, Generator, GlyphStyle, OutputFile,
+    };
 
     #[test]
     fn test_gen() {
@@ -218,6 +853,741 @@ mod tests {
             true,
         );
 
-        assert_eq!(ct, 404);
+        assert_eq!(ct, 409);
+    }
+
+    #[test]
+    fn test_gen_par() {
+        for (slots, extended) in [(5, false), (6, true), (7, false), (7, true)] {
+            let mut expected = vec![];
+            gen(slots, &mut |s| expected.push(s.to_string()), extended);
+            expected.sort();
+
+            let found = Mutex::new(vec![]);
+            gen_par(slots, extended, |s| {
+                found.lock().unwrap().push(s.to_string())
+            });
+            let mut found = found.into_inner().unwrap();
+            found.sort();
+
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    #[ignore = "generates the full maxi corpus"]
+    fn test_gen_par_maxi() {
+        let ct = std::sync::atomic::AtomicUsize::new(0);
+        gen_par(10, true, |_| {
+            ct.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        });
+        assert_eq!(ct.into_inner(), 2_177_736);
+    }
+
+    #[test]
+    fn test_iter_equations() {
+        let mut expected = vec![];
+        gen(6, &mut |s| expected.push(s.to_string()), true);
+        assert_eq!(iter_equations(6, true).collect::<Vec<_>>(), expected);
+
+        let divisions = iter_equations(8, false)
+            .filter(|e| e.contains('/'))
+            .take(3)
+            .collect::<Vec<_>>();
+        assert_eq!(divisions, vec!["111/3=37", "111/37=3", "112/14=8"]);
+    }
+
+    #[test]
+    fn test_generator_stop() {
+        // Nothing is accepted once `accept` asks to stop
+        let mut calls = 0;
+        let mut accept = |_: &mut [u8], _, _| {
+            calls += 1;
+            if calls < 3 {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        };
+        let mut g = Generator::new(8, &mut accept);
+        g.run(GenFeatures::extended(true));
+        assert!(g.stopped);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_styled_line_writer() {
+        for (style, expected) in [
+            (GlyphStyle::Unicode, "2²=4\n1+1=2\n2³=8\n"),
+            (GlyphStyle::Ascii, "2s=4\n1+1=2\n2c=8\n"),
+            (GlyphStyle::Caret, "2^2=4\n1+1=2\n2^3=8\n"),
+        ] {
+            let mut out = vec![];
+            let mut ct = 0;
+            {
+                let mut w = styled_line_writer(&mut out, &mut ct, style, None);
+                for s in ["2s=4", "1+1=2", "2c=8"] {
+                    w(s);
+                }
+            }
+            assert_eq!(ct, 3);
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_output_file_gzip() {
+        use std::io::Read;
+
+        let dir = std::env::temp_dir().join(format!("nerdle-gen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["micro.txt", "micro.txt.gz"] {
+            let path = dir.join(name);
+            let mut f = OutputFile::create(&path).unwrap();
+            let mut ct = 0;
+            gen(5, &mut line_writer(&mut f, &mut ct), false);
+            f.finish().unwrap();
+
+            let raw = std::fs::read(&path).unwrap();
+            let contents = if name.ends_with(".gz") {
+                let mut contents = String::new();
+                flate2::read::GzDecoder::new(&raw[..])
+                    .read_to_string(&mut contents)
+                    .unwrap();
+                contents
+            } else {
+                String::from_utf8(raw).unwrap()
+            };
+            assert_eq!(contents.lines().count(), 127);
+            assert!(contents.lines().any(|l| l == "1+1=2"));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_corpus() {
+        let dir = std::env::temp_dir().join(format!("nerdle-corpus-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("corpus.txt");
+        std::fs::write(
+            &path,
+            "1+1=2\n\n  2*3=6 \n1+1=3\n1+=23\n3²=9\n50000*50000/50000=50000\n",
+        )
+        .unwrap();
+        assert_eq!(
+            load_corpus(&path).unwrap(),
+            ["1+1=2", "2*3=6", "3²=9", "50000*50000/50000=50000"]
+        );
+        let unchecked = read_corpus(std::fs::read(&path).unwrap().as_slice(), false).unwrap();
+        assert_eq!(
+            unchecked,
+            [
+                "1+1=2",
+                "2*3=6",
+                "1+1=3",
+                "1+=23",
+                "3²=9",
+                "50000*50000/50000=50000"
+            ]
+        );
+        assert!(load_corpus(&dir.join("missing.txt")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_line_writer_progress() {
+        let mut out = vec![];
+        let mut ct = 0;
+        let mut seen = vec![];
+        {
+            let mut progress = |ct| seen.push(ct);
+            let mut w =
+                styled_line_writer(&mut out, &mut ct, GlyphStyle::Unicode, Some(&mut progress));
+            gen(5, &mut w, false);
+        }
+        assert_eq!(ct, 127);
+        assert_eq!(seen, (1..=127).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_count_equations() {
+        assert_eq!(count_equations(5, false), 127);
+        assert_eq!(count_equations(6, true), 409);
+        assert_eq!(count_equations(8, false), 17_723);
+    }
+
+    #[test]
+    #[ignore = "generates the full maxi corpus"]
+    fn test_count_equations_maxi() {
+        assert_eq!(count_equations(10, true), 2_177_736);
+    }
+
+    #[test]
+    fn test_expected_count() {
+        assert_eq!(expected_count(8, false), Some(17_723));
+        assert_eq!(expected_count(10, true), Some(2_177_736));
+        assert_eq!(expected_count(11, false), None);
+        for slots in 5..=8 {
+            for extended in [false, true] {
+                assert_eq!(
+                    expected_count(slots, extended),
+                    Some(count_equations(slots, extended)),
+                    "{} slots, extended: {}",
+                    slots,
+                    extended
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_gen_with_mask() {
+        let normalize = |s: &str| s.chars().map(normalize_power).collect::<String>();
+        for (slots, extended, mask) in [
+            (7, false, score("12+3=15", "10*5=50")),
+            (7, false, parse_mask_results("99/9=11", "BBBBGBB").unwrap()),
+            (6, true, score("2³-1=7", "3²-1=8")),
+            (6, true, score("2³/8=1", "3²-1=8")),
+        ] {
+            let mut expected = vec![];
+            gen(
+                slots,
+                &mut |s| {
+                    if matches_mask(&normalize(s), &mask) {
+                        expected.push(s.to_string());
+                    }
+                },
+                extended,
+            );
+            let mut actual = vec![];
+            gen_with_mask(slots, extended, &mask, &mut |s| actual.push(s.to_string()));
+            assert!(!expected.is_empty());
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_gen_template() {
+        let mut found = vec![];
+        gen_template("_+_=_", &mut |s| found.push(s.to_string()));
+        let mut expected = vec![];
+        for a in 1..10 {
+            for b in 1..10 - a {
+                expected.push(format!("{}+{}={}", a, b, a + b));
+            }
+        }
+        found.sort();
+        expected.sort();
+        assert_eq!(found.len(), 36);
+        assert_eq!(found, expected);
+
+        // The same as picking the equations of that shape from the full output
+        let shape = |s: &str| {
+            s.chars()
+                .map(|c| if c.is_ascii_digit() { '_' } else { c })
+                .collect::<String>()
+        };
+        for (template, extended) in [("__+_=__", false), ("_*_-_=_", false), ("_s+_=__", true)] {
+            let mut expected = vec![];
+            gen(
+                template.len(),
+                &mut |s| {
+                    if shape(s) == template {
+                        expected.push(s.to_string());
+                    }
+                },
+                extended,
+            );
+            let mut found = vec![];
+            gen_template(template, &mut |s| found.push(s.to_string()));
+            assert!(!found.is_empty(), "{}", template);
+            assert_eq!(found, expected, "{}", template);
+        }
+
+        // Fixed digits stay put
+        let mut found = vec![];
+        gen_template("1_+_=__", &mut |s| found.push(s.to_string()));
+        assert!(!found.is_empty());
+        assert!(found
+            .iter()
+            .all(|s| s.starts_with('1') && validate_equation(s) == Ok(true)));
+    }
+
+    #[test]
+    fn test_gen_canonical() {
+        for (slots, extended, collapsed) in [(7, false, 961), (7, true, 1169)] {
+            let mut all = vec![];
+            gen(slots, &mut |s| all.push(s.to_string()), extended);
+            let mut canonical = vec![];
+            gen_with_options(
+                slots,
+                GenOptions {
+                    features: GenFeatures::extended(extended),
+                    canonical: true,
+                    ..Default::default()
+                },
+                &mut |s| canonical.push(s.to_string()),
+            );
+            assert_eq!(all.len() - canonical.len(), collapsed);
+
+            // Every dropped equation is the swap of one which was kept
+            let kept = canonical.iter().collect::<HashSet<_>>();
+            for eq in all.iter().filter(|eq| !kept.contains(eq)) {
+                let (lhs, rhs) = eq.split_once('=').unwrap();
+                let idx = lhs.find(['+', '*']).unwrap();
+                let swapped = format!(
+                    "{}{}{}={}",
+                    &lhs[idx + 1..],
+                    &lhs[idx..idx + 1],
+                    &lhs[..idx],
+                    rhs
+                );
+                assert!(kept.contains(&swapped), "{} -> {}", eq, swapped);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gen_for_target() {
+        for (slots, extended, target) in [(7, false, 42), (6, true, 8), (6, true, 0)] {
+            let mut expected = vec![];
+            gen(
+                slots,
+                &mut |s| {
+                    if s.ends_with(&format!("={}", target)) {
+                        expected.push(s.to_string());
+                    }
+                },
+                extended,
+            );
+            let mut actual = vec![];
+            gen_for_target(slots, extended, target, &mut |s| actual.push(s.to_string()));
+            assert!(!expected.is_empty());
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_gen_equals_count() {
+        let mut found = vec![];
+        gen_with_options(
+            5,
+            GenOptions {
+                equals_count: 2,
+                ..Default::default()
+            },
+            &mut |s| found.push(s.to_string()),
+        );
+        assert_eq!(found.len(), 9);
+        assert!(found.contains(&"1=1=1".to_string()));
+
+        for (slots, extended) in [(7, false), (7, true)] {
+            let mut found = vec![];
+            gen_with_options(
+                slots,
+                GenOptions {
+                    features: GenFeatures::extended(extended),
+                    equals_count: 2,
+                    ..Default::default()
+                },
+                &mut |s| found.push(s.to_string()),
+            );
+            assert!(found.contains(&"1+2=3=3".to_string()));
+            assert!(found.contains(&"4=2*2=4".to_string()));
+            for eq in found {
+                let segments = eq.split('=').collect::<Vec<_>>();
+                assert_eq!(segments.len(), 3, "{}", eq);
+                let rhs = segments[2].parse::<i32>().unwrap();
+                for segment in &segments[..2] {
+                    assert_eq!(eval(segment), Ok(rhs), "{}", eq);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gen_allow_negative() {
+        let negative = GenOptions {
+            allow_negative: true,
+            ..Default::default()
+        };
+
+        // The shortest negative equation is `1-2=-1`, so this doesn't change
+        // anything until there are 6 slots
+        let mut ct = 0;
+        gen_with_options(5, negative, &mut |_| ct += 1);
+        assert_eq!(ct, 127);
+
+        let mut found = vec![];
+        gen_with_options(6, negative, &mut |s| found.push(s.to_string()));
+        assert_eq!(found.iter().filter(|s| !s.contains("=-")).count(), 206);
+        assert!(found.contains(&"1-5=-4".to_string()));
+        for eq in found.iter().filter(|s| s.contains("=-")) {
+            let (lhs, rhs) = eq.split_once('=').unwrap();
+            assert_eq!(eval(lhs), Ok(rhs.parse().unwrap()), "{}", eq);
+        }
+    }
+
+    // Every equation `gen` emits must hold when its sides are evaluated
+    // independently, with the canonical spelling of its value on the right
+    fn check_gen_evaluates(slots: usize, extended: bool) {
+        gen_par(slots, extended, |eq| {
+            assert_eq!(eq.chars().count(), slots, "{}", eq);
+            let (lhs, rhs) = eq.split_once('=').unwrap();
+            let v = rhs.parse::<i32>().unwrap();
+            assert_eq!(v.to_string(), rhs, "{}", eq);
+            assert_eq!(eval(lhs), Ok(v), "{}", eq);
+            assert_eq!(eval_ast(&parse(lhs).unwrap()), Ok(v), "{}", eq);
+            assert_eq!(validate_equation(eq), Ok(true), "{}", eq);
+        });
+    }
+
+    #[test]
+    fn test_gen_evaluates() {
+        for slots in 3..=8 {
+            for extended in [false, true] {
+                check_gen_evaluates(slots, extended);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "generates the 9-slot and full maxi corpora"]
+    fn test_gen_evaluates_maxi() {
+        check_gen_evaluates(9, false);
+        check_gen_evaluates(9, true);
+        check_gen_evaluates(10, false);
+        check_gen_evaluates(10, true);
+    }
+
+    #[test]
+    fn test_collect_equations() {
+        let equations = collect_equations(5, false);
+        assert_eq!(equations.len(), 127);
+        assert!(equations.contains(&"1+1=2".to_string()));
+
+        let equations = collect_equations(6, true);
+        assert_eq!(equations.len(), 409);
+        assert!(equations.contains(&"1+2²=5".to_string()));
+        assert!(equations.iter().all(|s| !s.contains(['s', 'c'])));
+    }
+
+    #[test]
+    fn test_gen_sorted() {
+        // `gen` isn't sorted by itself
+        assert!(!collect_equations(5, false).is_sorted());
+
+        for (slots, extended) in [(5, false), (6, true), (7, false)] {
+            let sorted = gen_sorted(slots, extended);
+            assert!(sorted.is_sorted());
+            assert!(sorted.windows(2).all(|w| w[0] != w[1]));
+            assert_eq!(Some(sorted.len()), expected_count(slots, extended));
+            for e in collect_equations(slots, extended) {
+                assert!(sorted.binary_search(&e).is_ok());
+            }
+        }
+        let sorted = gen_sorted(6, true);
+        assert!(sorted.binary_search(&"1+2²=5".to_string()).is_ok());
+        assert!(sorted.binary_search(&"1+2²=6".to_string()).is_err());
+    }
+
+    // Call `f` on every string of `len` bytes from `alphabet`, after `lhs`
+    fn visit(lhs: &mut Vec<u8>, len: usize, alphabet: &[u8], f: &mut dyn FnMut(&[u8])) {
+        if lhs.len() == len {
+            f(lhs);
+            return;
+        }
+        for &b in alphabet {
+            lhs.push(b);
+            visit(lhs, len, alphabet, f);
+            lhs.pop();
+        }
+    }
+
+    #[test]
+    fn test_gen_matches_brute_force() {
+        // Every string over the non-extended alphabet which evaluates to a
+        // value of the right width. Nerdle needs an operator, and a lone zero
+        // is never generated as an operand.
+        let slots = 7;
+        let alphabet = b"0123456789+-*/";
+        let mut expected = vec![];
+        let mut lhs = vec![];
+        for len in 1..slots - 1 {
+            visit(&mut lhs, len, alphabet, &mut |lhs| {
+                let s = std::str::from_utf8(lhs).unwrap();
+                if !s.contains(['+', '-', '*', '/'])
+                    || s.split(['+', '-', '*', '/']).any(|n| n == "0")
+                {
+                    return;
+                }
+                if let Ok(v) = eval(s) {
+                    let eq = format!("{}={}", s, v);
+                    if v >= 0 && eq.len() == slots {
+                        expected.push(eq);
+                    }
+                }
+            });
+        }
+
+        let mut actual = vec![];
+        gen(slots, &mut |s| actual.push(s.to_string()), false);
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_gen_extended_matches_brute_force() {
+        // As above, but with parentheses, squares and cubes. A number may be
+        // squared or cubed on its own, as in `3³=27`, but two powers in a row
+        // are never generated.
+        let alphabet = b"0123456789+-*/()sc";
+        for slots in [5, 6] {
+            let mut expected = vec![];
+            let mut lhs = vec![];
+            for len in 1..slots - 1 {
+                visit(&mut lhs, len, alphabet, &mut |lhs| {
+                    let s = std::str::from_utf8(lhs).unwrap();
+                    if s.bytes().all(|b| b.is_ascii_digit())
+                        || s.split(|c: char| !c.is_ascii_digit())
+                            .any(|n| n.starts_with('0'))
+                        || ["ss", "sc", "cs", "cc"].iter().any(|p| s.contains(p))
+                    {
+                        return;
+                    }
+                    if let Ok(v) = eval(s) {
+                        let eq = format!("{}={}", s, v);
+                        if v >= 0 && eq.len() == slots {
+                            expected.push(eq);
+                        }
+                    }
+                });
+            }
+
+            let mut actual = vec![];
+            gen(slots, &mut |s| actual.push(s.to_string()), true);
+            expected.sort();
+            actual.sort();
+            assert_eq!(actual, expected, "{} slots", slots);
+        }
+        assert!(collect_equations(5, true).contains(&"3³=27".to_string()));
+        assert!(collect_equations(6, true).contains(&"9³=729".to_string()));
+    }
+
+    #[test]
+    fn test_gen_features() {
+        // Parentheses don't fit in any 6-slot equation, so use 7 slots
+        for (paren, square, cube, expected) in [
+            (false, false, false, 6661),
+            (true, false, false, 7141),
+            (false, true, false, 7152),
+            (false, false, true, 6873),
+            (true, true, false, 7645),
+            (true, false, true, 7358),
+            (false, true, true, 7408),
+            (true, true, true, 7908),
+        ] {
+            let features = GenFeatures {
+                paren,
+                square,
+                cube,
+            };
+            let mut found = vec![];
+            gen_with_options(
+                7,
+                GenOptions {
+                    features,
+                    ..Default::default()
+                },
+                &mut |s| found.push(s.to_string()),
+            );
+            assert_eq!(found.len(), expected, "{:?}", features);
+            assert_eq!(found.iter().any(|s| s.contains('(')), paren);
+            assert_eq!(found.iter().any(|s| s.contains('s')), square);
+            assert_eq!(found.iter().any(|s| s.contains('c')), cube);
+        }
+    }
+
+    #[test]
+    fn test_gen_max_depth() {
+        fn depth(eq: &str) -> usize {
+            eq.chars()
+                .scan(0usize, |d, c| {
+                    match c {
+                        '(' => *d += 1,
+                        ')' => *d -= 1,
+                        _ => (),
+                    }
+                    Some(*d)
+                })
+                .max()
+                .unwrap_or(0)
+        }
+
+        // Only 15 of the 29,139 extended classic equations nest parentheses
+        let mut ct = 0;
+        gen_with_options(
+            8,
+            GenOptions {
+                features: GenFeatures::extended(true),
+                max_depth: 1,
+                ..Default::default()
+            },
+            &mut |s| {
+                assert!(depth(s) <= 1, "{}", s);
+                ct += 1;
+            },
+        );
+        assert_eq!(ct, 29_124);
+        assert_eq!(expected_count(8, true), Some(29_139));
+
+        let mut unlimited = vec![];
+        gen(7, &mut |s| unlimited.push(s.to_string()), true);
+        assert!(unlimited.iter().any(|s| depth(s) == 2));
+        for max_depth in [0, 1, 2] {
+            let mut found = vec![];
+            gen_with_options(
+                7,
+                GenOptions {
+                    features: GenFeatures::extended(true),
+                    max_depth,
+                    ..Default::default()
+                },
+                &mut |s| found.push(s.to_string()),
+            );
+            let expected = unlimited
+                .iter()
+                .filter(|s| depth(s) <= max_depth)
+                .cloned()
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected, "max depth {}", max_depth);
+        }
+    }
+
+    #[test]
+    fn test_gen_allowed_digits() {
+        for (slots, extended) in [(6, false), (6, true)] {
+            let mut expected = vec![];
+            gen(slots, &mut |s| expected.push(s.to_string()), extended);
+            expected.retain(|s| !s.contains('0'));
+
+            let mut allowed_digits = [true; 10];
+            allowed_digits[0] = false;
+            let mut found = vec![];
+            gen_with_options(
+                slots,
+                GenOptions {
+                    features: GenFeatures::extended(extended),
+                    allowed_digits,
+                    ..Default::default()
+                },
+                &mut |s| found.push(s.to_string()),
+            );
+            assert_eq!(found, expected);
+        }
+
+        // Banned digits are left out of every expression and value
+        let mut found = vec![];
+        gen_with_options(
+            5,
+            GenOptions {
+                allowed_digits: [
+                    true, false, false, false, true, true, true, true, true, true,
+                ],
+                equals_count: 2,
+                ..Default::default()
+            },
+            &mut |s| found.push(s.to_string()),
+        );
+        assert!(found.contains(&"4=4=4".to_string()));
+        assert!(found.iter().all(|s| !s.contains(['1', '2', '3'])));
+    }
+
+    #[test]
+    fn test_gen_exclude_trivial() {
+        let options = GenOptions {
+            exclude_trivial: true,
+            ..Default::default()
+        };
+        let mut found = vec![];
+        gen_with_options(5, options, &mut |s| found.push(s.to_string()));
+        // 9 each of `x*1=x`, `1*x=x`, and `x/1=x`, where `1*1=1` is in the
+        // first two
+        assert_eq!(found.len(), 127 - 26);
+        assert!(!found.contains(&"5*1=5".to_string()));
+        assert!(found.contains(&"1+4=5".to_string()));
+
+        for (eq, trivial) in [
+            ("5*1", true),
+            ("1*5+2", true),
+            ("2+5/1", true),
+            ("5+0", true),
+            ("5-0", true),
+            ("0-5", false),
+            ("1/5", false),
+            ("5*1s", false),
+            ("5*(1)", false),
+            ("(5*1)+2", false),
+            ("11*3", false),
+            ("1+2=3*1", true),
+        ] {
+            assert_eq!(is_trivial(eq.as_bytes()), trivial, "{}", eq);
+        }
+    }
+
+    #[test]
+    fn test_gen_valued() {
+        for (slots, extended) in [(6, false), (6, true)] {
+            let mut expected = vec![];
+            gen(slots, &mut |s| expected.push(s.to_string()), extended);
+            let mut actual = vec![];
+            gen_valued(slots, extended, &mut |s, v| {
+                let (lhs, rhs) = s.split_once('=').unwrap();
+                assert_eq!(eval(lhs), Ok(v), "{}", s);
+                assert_eq!(rhs.parse(), Ok(v), "{}", s);
+                actual.push(s.to_string());
+            });
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_group_by_value() {
+        let groups = group_by_value(5, false);
+        assert_eq!(groups.values().map(|g| g.len()).sum::<usize>(), 127);
+        assert_eq!(
+            groups.keys().copied().collect::<Vec<_>>(),
+            (0..=9).collect::<Vec<_>>()
+        );
+        for (v, equations) in &groups {
+            for eq in equations {
+                assert!(eq.ends_with(&format!("={}", v)), "{}", eq);
+            }
+        }
+
+        let groups = group_by_value(6, true);
+        assert!(groups[&9].contains(&"1+2³=9".to_string()));
+    }
+
+    #[test]
+    fn test_gen_range() {
+        for (slots, extended) in [(6, false), (6, true)] {
+            let mut expected = vec![];
+            gen(slots, &mut |s| expected.push(s.to_string()), extended);
+
+            let mut actual = vec![];
+            for range in [0..1, 1..4, 4..7, 7..10] {
+                gen_range(slots, extended, range, &mut |s| actual.push(s.to_string()));
+            }
+            expected.sort();
+            actual.sort();
+            assert_eq!(actual, expected);
+        }
+
+        let mut sevens = vec![];
+        gen_range(6, false, 7..8, &mut |s| sevens.push(s.to_string()));
+        assert!(!sevens.is_empty());
+        assert!(sevens.iter().all(|s| s.starts_with('7')));
     }
 }
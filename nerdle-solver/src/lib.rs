@@ -1,3 +1,22 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+#[macro_use]
+mod macros;
+
+#[cfg(feature = "corpora")]
+pub mod corpus;
 pub mod eval;
+#[cfg(feature = "std")]
 pub mod gen;
+#[cfg(feature = "std")]
 pub mod mask;
+#[cfg(feature = "std")]
+pub mod matrix;
+#[cfg(feature = "std")]
+pub mod solver;
+
+#[cfg(feature = "std")]
+pub use solver::Solver;
@@ -0,0 +1,24 @@
+//! Logging macros which go through `tracing` with the `tracing` feature, so
+//! embedders can control verbosity, and fall back to stderr without it.
+
+// Report a problem through `tracing` with the `tracing` feature, or print it to
+// stderr without
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        eprintln!($($arg)*);
+    }};
+}
+
+// Report progress through `tracing` with the `tracing` feature, and otherwise
+// stay quiet
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
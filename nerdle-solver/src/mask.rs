@@ -1,54 +1,357 @@
-use std::collections::{BTreeSet, HashMap};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use rayon::prelude::*;
 
-#[derive(Clone, Default, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Masks {
     correct: BTreeSet<(usize, char)>,
     incorrect: BTreeSet<(usize, char)>,
     not_present: BTreeSet<(usize, char)>,
+    /// The minimum and (if known) maximum number of copies of each char, when
+    /// they can't be worked out from the entries above, as for masks built by
+    /// `merge` or parsed with `?` positions.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    counts: Option<CharCounts>,
 }
 
-// Parse the provided string to an evaluation mask
+type CharCounts = BTreeMap<char, (usize, Option<usize>)>;
+
+const MAX_PACKED_LEN: usize = 16;
+const PACKED_GREEN: u32 = 2;
+const PACKED_PURPLE: u32 = 1;
+
+impl Masks {
+    /// The chars known to be in the right position (green)
+    pub fn correct(&self) -> impl Iterator<Item = (usize, char)> + '_ {
+        self.correct.iter().copied()
+    }
+
+    /// The chars known to be in the equation, but not at these positions
+    /// (purple)
+    pub fn incorrect(&self) -> impl Iterator<Item = (usize, char)> + '_ {
+        self.incorrect.iter().copied()
+    }
+
+    /// The chars which aren't at these positions, and have no more copies in
+    /// the equation than are green or purple elsewhere (black)
+    pub fn not_present(&self) -> impl Iterator<Item = (usize, char)> + '_ {
+        self.not_present.iter().copied()
+    }
+
+    /// The positions whose char is known, sorted by position
+    pub fn known_positions(&self) -> Vec<(usize, char)> {
+        self.correct().collect()
+    }
+
+    /// Whether this is the feedback for guessing the solution of a `len`-char
+    /// equation, with every position green
+    pub fn is_solved(&self, len: usize) -> bool {
+        self.correct.len() == len && self.incorrect.is_empty() && self.not_present.is_empty()
+    }
+
+    /// A mask for equations shaped like `template`, where each `_` is a digit
+    /// and every other char is fixed in place, so no other symbols appear.
+    pub(crate) fn from_template(template: &str) -> Self {
+        let mut masks = Masks::default();
+        for (idx, c) in template.chars().enumerate() {
+            if c == '_' {
+                for sym in ['+', '-', '*', '/', '=', '(', ')', '²', '³'] {
+                    masks.not_present.insert((idx, sym));
+                }
+            } else {
+                masks.correct.insert((idx, normalize_power(c)));
+            }
+        }
+        masks
+    }
+
+    /// Write the state of the first `len` positions as a `G` (green), `P`
+    /// (purple) or `B` (black) for each, or `?` where this doesn't say, in the
+    /// format accepted by `parse_mask_results`. Like `pack`, this only makes
+    /// sense for masks describing a single guess; `feedback_letters` also
+    /// checks the chars against the guess.
+    pub fn to_gpb(&self, len: usize) -> String {
+        let at = |set: &BTreeSet<(usize, char)>, idx| {
+            set.range((idx, char::MIN)..=(idx, char::MAX))
+                .next()
+                .is_some()
+        };
+        (0..len)
+            .map(|idx| {
+                if at(&self.correct, idx) {
+                    'G'
+                } else if at(&self.incorrect, idx) {
+                    'P'
+                } else if at(&self.not_present, idx) {
+                    'B'
+                } else {
+                    '?'
+                }
+            })
+            .collect()
+    }
+
+    /// Pack the state of the first `len` positions into an integer, using two
+    /// bits per position starting from the lowest: 2 for green, 1 for purple,
+    /// and 0 for black. This is only a faithful encoding for masks describing
+    /// a single guess, like those from `score` or `parse_mask_results`.
+    ///
+    /// Panics if `len` is more than 16.
+    pub fn pack(&self, len: usize) -> u32 {
+        assert!(len <= MAX_PACKED_LEN, "can't pack more than 16 positions");
+        let mut packed = 0;
+        for &(idx, _) in &self.correct {
+            if idx < len {
+                packed |= PACKED_GREEN << (2 * idx);
+            }
+        }
+        for &(idx, _) in &self.incorrect {
+            if idx < len {
+                packed |= PACKED_PURPLE << (2 * idx);
+            }
+        }
+        packed
+    }
+
+    /// The minimum and (if known) maximum number of copies of each char in the
+    /// equation. Green and purple entries each add one to the minimum, and a
+    /// black entry means there are no more copies than that.
+    fn count_bounds(&self) -> Cow<'_, CharCounts> {
+        if let Some(counts) = &self.counts {
+            return Cow::Borrowed(counts);
+        }
+        let mut counts = CharCounts::new();
+        for (_, c) in self.correct.iter().chain(&self.incorrect) {
+            counts.entry(*c).or_default().0 += 1;
+        }
+        for (_, c) in &self.not_present {
+            let bounds = counts.entry(*c).or_default();
+            bounds.1 = Some(bounds.0);
+        }
+        Cow::Owned(counts)
+    }
+
+    /// Whether `c` could appear at `idx` in an equation matching this mask,
+    /// considering only the constraints which apply to single positions.
+    pub(crate) fn allows(&self, idx: usize, c: char) -> bool {
+        if let Some(&(_, fixed)) = self
+            .correct
+            .range((idx, char::MIN)..=(idx, char::MAX))
+            .next()
+        {
+            return fixed == c;
+        }
+        if self.incorrect.contains(&(idx, c)) || self.not_present.contains(&(idx, c)) {
+            return false;
+        }
+        if let Some(counts) = &self.counts {
+            // A char whose copies are all green can't appear anywhere else
+            let green = self.correct.iter().filter(|&&(_, x)| x == c).count();
+            return counts.get(&c).and_then(|b| b.1) != Some(green);
+        }
+        // A char which was black somewhere and never purple can only appear in
+        // positions where it's green
+        !self.not_present.iter().any(|&(_, x)| x == c)
+            || self.incorrect.iter().any(|&(_, x)| x == c)
+    }
+}
+
+/// Combine the feedback from several guesses into one mask, which an equation
+/// matches exactly when it matches every one of `masks`.
+///
+/// The green, purple, and black entries are combined by taking their union,
+/// since each rules out or fixes a char at one position regardless of which
+/// guess it came from. The number of copies of each char can't be combined the
+/// same way: if one guess has a purple `1` at position 0 and another has one
+/// at position 2, the equation might have just one `1`. So instead the merged
+/// mask keeps the largest minimum and smallest maximum count for each char,
+/// e.g. two purple `1`s in one guess and a purple and a black `1` in another
+/// pin down exactly two copies.
+pub fn merge(masks: &[Masks]) -> Masks {
+    let mut merged = Masks::default();
+    let mut counts = CharCounts::new();
+    for m in masks {
+        merged.correct.extend(&m.correct);
+        merged.incorrect.extend(&m.incorrect);
+        merged.not_present.extend(&m.not_present);
+        for (&c, &(min, max)) in m.count_bounds().iter() {
+            let bounds = counts.entry(c).or_default();
+            bounds.0 = bounds.0.max(min);
+            bounds.1 = match (bounds.1, max) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+    }
+    // Masks which only differ in how they were built should be equal
+    if *merged.count_bounds() != counts {
+        merged.counts = Some(counts);
+    }
+    merged
+}
+
+// Parse the provided string to an evaluation mask, or describe why it can't be
+// parsed
 //
 // Correct location is represented by '2', 'C', 'c', 'G', 'g'
 // Incorrect location is represented by '1', 'I', 'i', 'P', 'p'
 // Not present is represented by '0', 'N', 'n', 'B', 'b', 'R', 'r', ' '
 //
+// The colored tiles from a shared result also work: '🟩' or '🟢' for correct,
+// '🟪' or '🟣' for incorrect, and '⬛', '⬜' or '⚫' for not present. Emoji
+// variation selectors are ignored, so pasted tiles line up with the guess.
+//
+// A '?' marks a position whose color isn't known, which is left out of the mask
+// entirely. It still loosens the number of copies a black entry allows: with
+// `?B` for `11`, the solution may have a `1` in the first position.
+//
 // Guesses will be normalized to use the square/cubed characters rather than the
 // s and c characters.
-pub fn parse_mask_results(guess: &str, mask: &str) -> Option<Masks> {
+pub fn parse_mask(guess: &str, mask: &str) -> Result<Masks, String> {
     let mut masks = Masks::default();
+    let mask = mask.replace('\u{fe0f}', "");
+    let mask = mask.as_str();
 
     if mask.chars().count() != guess.chars().count() {
-        eprintln!(
+        return Err(format!(
             "Mask length doesn't match guess length! mask: {} guess: {}",
             mask, guess
-        );
-        return None;
+        ));
     }
 
+    let mut unknown = vec![];
     for (idx, (m, c)) in mask.chars().zip(guess.chars()).enumerate() {
         match m {
-            '2' | 'C' | 'c' | 'G' | 'g' => {
+            '2' | 'C' | 'c' | 'G' | 'g' | '🟩' | '🟢' => {
                 masks.correct.insert((idx, normalize_power(c)));
             }
-            '1' | 'I' | 'i' | 'P' | 'p' => {
+            '1' | 'I' | 'i' | 'P' | 'p' | '🟪' | '🟣' => {
                 masks.incorrect.insert((idx, normalize_power(c)));
             }
-            '0' | 'N' | 'n' | 'B' | 'b' | 'R' | 'r' | ' ' => {
+            '0' | 'N' | 'n' | 'B' | 'b' | 'R' | 'r' | ' ' | '⬛' | '⬜' | '⚫' => {
                 masks.not_present.insert((idx, normalize_power(c)));
             }
+            '?' => unknown.push(normalize_power(c)),
             _ => {
-                eprintln!("Incorrect mask char '{}' in \"{}\"", c, mask);
-                return None;
+                return Err(format!("Incorrect mask char '{}' in \"{}\"", m, mask));
             }
         }
     }
+
+    // Each unknown copy of a char might be one more green or purple copy
+    let mut counts = masks.count_bounds().into_owned();
+    for c in unknown {
+        if let Some((_, Some(max))) = counts.get_mut(&c) {
+            *max += 1;
+        }
+    }
+    if *masks.count_bounds() != counts {
+        masks.counts = Some(counts);
+    }
+    Ok(masks)
+}
+
+/// Like `parse_mask`, but reports the problem as a warning (printed to stderr
+/// without the `tracing` feature) rather than returning it
+pub fn parse_mask_results(guess: &str, mask: &str) -> Option<Masks> {
+    parse_mask(guess, mask).map_err(|e| warn!("{}", e)).ok()
+}
+
+/// Check that `guess` is a `len`-char equation which holds, after replacing
+/// `s` and `c` with ² and ³, describing the problem if not.
+pub fn check_guess(guess: &str, len: usize) -> Result<(), String> {
+    let guess = guess.chars().map(normalize_power).collect::<String>();
+    let guess_len = guess.chars().count();
+    if guess_len != len {
+        return Err(format!(
+            "{} has {} chars, but equations have {}",
+            guess, guess_len, len
+        ));
+    }
+    match crate::eval::validate_equation(&guess) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("{} isn't a true equation", guess)),
+        Err(e) => Err(format!("{} isn't a valid equation: {}", guess, e)),
+    }
+}
+
+/// Like `parse_mask_results`, but also rejects guesses which `check_guess`
+/// doesn't accept as `len`-char equations, rather than producing a
+/// meaningless mask.
+pub fn parse_mask_results_strict(guess: &str, mask: &str, len: usize) -> Option<Masks> {
+    if let Err(e) = check_guess(guess, len) {
+        warn!("{}", e);
+        return None;
+    }
+    parse_mask_results(guess, mask)
+}
+
+/// Write `mask` as a string which `masks_from_string` can parse back.
+///
+/// Each entry is written as `g`, `p`, or `b` (for green, purple, and black),
+/// followed by the index, a `:`, and the char, e.g. `g0:1 p3:= b5:²`. Entries
+/// are separated by spaces and sorted, so equal masks give equal strings.
+///
+/// Masks built by `merge` may also have entries like `n1-2:1` for the minimum
+/// and maximum number of copies of a char, where the maximum may be left empty.
+pub fn masks_to_string(mask: &Masks) -> String {
+    let mut entries = vec![];
+    for (kind, set) in [
+        ('g', &mask.correct),
+        ('p', &mask.incorrect),
+        ('b', &mask.not_present),
+    ] {
+        for (idx, c) in set {
+            entries.push(format!("{}{}:{}", kind, idx, c));
+        }
+    }
+    for (c, (min, max)) in mask.counts.iter().flatten() {
+        let max = max.map(|max| max.to_string()).unwrap_or_default();
+        entries.push(format!("n{}-{}:{}", min, max, c));
+    }
+    entries.join(" ")
+}
+
+/// Parse a string written by `masks_to_string`.
+pub fn masks_from_string(s: &str) -> Option<Masks> {
+    let mut masks = Masks::default();
+    for entry in s.split_whitespace() {
+        let mut chars = entry.chars();
+        let kind = chars.next()?;
+        let (idx, c) = chars.as_str().split_once(':')?;
+        let mut c = c.chars();
+        let (Some(c), None) = (c.next(), c.next()) else {
+            return None;
+        };
+        let set = match kind {
+            'g' => &mut masks.correct,
+            'p' => &mut masks.incorrect,
+            'b' => &mut masks.not_present,
+            'n' => {
+                let (min, max) = idx.split_once('-')?;
+                let max = if max.is_empty() {
+                    None
+                } else {
+                    Some(max.parse().ok()?)
+                };
+                let counts = masks.counts.get_or_insert_with(CharCounts::new);
+                counts.insert(c, (min.parse().ok()?, max));
+                continue;
+            }
+            _ => return None,
+        };
+        set.insert((idx.parse().ok()?, c));
+    }
     Some(masks)
 }
 
-fn normalize_power(c: char) -> char {
+pub(crate) fn normalize_power(c: char) -> char {
     match c {
         's' => '²',
         'c' => '³',
@@ -56,9 +359,18 @@ fn normalize_power(c: char) -> char {
     }
 }
 
+/// Whether `guess` could be the solution, given the feedback in `mask`. As in
+/// `parse_mask_results`, `s` and `c` in `guess` are treated as ² and ³.
+///
+/// The `=` sign is treated like any other char, which already uses everything
+/// its tile says: every equation has one, so in a corpus where it moves
+/// around, a green `=` keeps only the equations with it in that position and a
+/// purple `=` drops them. It's never black unless the guess has more `=`
+/// signs than the solution.
 pub fn matches_mask(guess: &str, mask: &Masks) -> bool {
     let chars = guess
         .chars()
+        .map(normalize_power)
         .enumerate()
         .collect::<BTreeSet<(usize, char)>>();
 
@@ -73,35 +385,128 @@ pub fn matches_mask(guess: &str, mask: &Masks) -> bool {
     }
 
     let mut char_counts: HashMap<char, usize> = HashMap::new();
-    for (idx, c) in guess.chars().enumerate() {
-        if !mask.correct.contains(&(idx, c)) {
-            *char_counts.entry(c).or_default() += 1;
-        }
+    for &(_, c) in &chars {
+        *char_counts.entry(c).or_default() += 1;
     }
+    mask.count_bounds().iter().all(|(c, (min, max))| {
+        let ct = char_counts.get(c).copied().unwrap_or(0);
+        ct >= *min && max.is_none_or(|max| ct <= max)
+    })
+}
 
-    for (_, c) in &mask.incorrect {
-        let ct = char_counts.entry(*c).or_default();
-        if *ct == 0 {
-            return false;
-        }
-        *ct -= 1;
+/// Explain why `matches_mask` rejects `guess`, or return `None` if it
+/// doesn't. The reason is the first check which fails, in the same order as
+/// `matches_mask`, with positions counted from 1.
+pub fn explain_mismatch(guess: &str, mask: &Masks) -> Option<String> {
+    let chars = guess
+        .chars()
+        .map(normalize_power)
+        .enumerate()
+        .collect::<BTreeSet<(usize, char)>>();
+
+    if let Some((idx, c)) = mask.not_present.intersection(&chars).next() {
+        return Some(format!(
+            "char `{}` at position {} should not be present",
+            c,
+            idx + 1
+        ));
     }
-    for (_, c) in &mask.not_present {
-        if let Some(ct) = char_counts.get(c) {
-            if *ct > 0 {
-                return false;
-            }
+    if let Some((idx, c)) = mask.incorrect.intersection(&chars).next() {
+        return Some(format!(
+            "char `{}` at position {} should be in another position",
+            c,
+            idx + 1
+        ));
+    }
+    if let Some((idx, c)) = mask.correct.difference(&chars).next() {
+        return Some(format!(
+            "missing required green `{}` at position {}",
+            c,
+            idx + 1
+        ));
+    }
+
+    let mut char_counts: HashMap<char, usize> = HashMap::new();
+    for &(_, c) in &chars {
+        *char_counts.entry(c).or_default() += 1;
+    }
+    for (c, (min, max)) in mask.count_bounds().iter() {
+        let ct = char_counts.get(c).copied().unwrap_or(0);
+        if ct < *min {
+            return Some(format!(
+                "should have at least {} `{}`, but has {}",
+                min, c, ct
+            ));
+        }
+        if let Some(max) = max.filter(|max| ct > *max) {
+            return Some(format!(
+                "should have at most {} `{}`, but has {}",
+                max, c, ct
+            ));
         }
     }
+    None
+}
 
-    true
+/// Whether `guess` is allowed in hard mode after the feedback in `mask`: it
+/// must keep every green char in its position, and use every purple char, with
+/// at least as many copies as have been revealed.
+///
+/// Unlike `matches_mask`, this doesn't rule out guesses which repeat a purple
+/// char in the same position, or use black chars.
+pub fn satisfies_hard_mode(guess: &str, mask: &Masks) -> bool {
+    let guess = guess.chars().map(normalize_power).collect::<Vec<_>>();
+    if !mask
+        .correct
+        .iter()
+        .all(|(idx, c)| guess.get(*idx) == Some(c))
+    {
+        return false;
+    }
+    mask.count_bounds()
+        .iter()
+        .all(|(c, (min, _))| guess.iter().filter(|g| *g == c).count() >= *min)
 }
 
+/// Score `guess` against `truth` the way Nerdle colors tiles.
+///
+/// Duplicate characters follow Nerdle's rules:
+/// - a char in the right position is always green
+/// - otherwise, each of the remaining (non-green) copies of a char in `truth`
+///   turns at most one non-green copy in `guess` purple, assigned from left to
+///   right
+/// - any further copies in `guess` are black, even if the same char is green
+///   elsewhere
+///
+/// So the number of green and purple copies of a char is the number of copies
+/// in `truth`, capped by the number in `guess`, and a black copy means `truth`
+/// has no more than that. This makes `score` consistent with `matches_mask`:
+/// `matches_mask(t, &score(g, truth))` holds exactly when
+/// `score(g, t) == score(g, truth)`.
+///
+/// As in `parse_mask_results`, `s` and `c` are treated as ² and ³ in both
+/// `guess` and `truth`, and the mask uses ² and ³.
+///
+/// Panics if `guess` and `truth` have different numbers of chars, since
+/// Nerdle's guesses are always the same length as the solution and there's no
+/// meaningful mask otherwise.
 pub fn score(guess: &str, truth: &str) -> Masks {
+    assert_eq!(
+        guess.chars().count(),
+        truth.chars().count(),
+        "can't score {} against {} of a different length",
+        guess,
+        truth
+    );
     let mut m = Masks::default();
 
+    let guess = guess.chars().map(normalize_power).collect::<Vec<_>>();
     let mut truth_char_counts: HashMap<char, usize> = HashMap::new();
-    for (idx, (g, t)) in guess.chars().zip(truth.chars()).enumerate() {
+    for (idx, (&g, t)) in guess
+        .iter()
+        .zip(truth.chars().map(normalize_power))
+        .enumerate()
+    {
         if g == t {
             m.correct.insert((idx, g));
         } else {
@@ -109,7 +514,7 @@ pub fn score(guess: &str, truth: &str) -> Masks {
         }
     }
 
-    for (idx, g) in guess.chars().enumerate() {
+    for (idx, g) in guess.into_iter().enumerate() {
         if m.correct.contains(&(idx, g)) {
             continue;
         }
@@ -125,66 +530,1268 @@ pub fn score(guess: &str, truth: &str) -> Masks {
     m
 }
 
-/// Compute the guess which has the highest entropy in the corpus
-pub fn compute_best_guess<T: AsRef<str> + Sync>(corpus: &[T]) -> (&str, f64) {
-    corpus
+/// Render the feedback the game would show for `guess` when the solution is
+/// `truth`, as a `G` (green), `P` (purple) or `B` (black) for each position of
+/// `guess`. This is in the format accepted by `parse_mask_results`.
+pub fn explain(guess: &str, truth: &str) -> String {
+    feedback_letters(guess, &score(guess, truth))
+}
+
+// The color of the tile for `c` at `idx` in `mask`, as a letter accepted by
+// `parse_mask_results`
+fn tile(mask: &Masks, idx: usize, c: char) -> char {
+    let c = normalize_power(c);
+    if mask.correct.contains(&(idx, c)) {
+        'G'
+    } else if mask.incorrect.contains(&(idx, c)) {
+        'P'
+    } else if mask.not_present.contains(&(idx, c)) {
+        'B'
+    } else {
+        '?'
+    }
+}
+
+/// Render `mask`, the feedback for `guess`, as a `G` (green), `P` (purple) or
+/// `B` (black) for each position of `guess`, or `?` where `mask` doesn't say.
+/// This is in the format accepted by `parse_mask_results`.
+pub fn feedback_letters(guess: &str, mask: &Masks) -> String {
+    guess
+        .chars()
+        .enumerate()
+        .map(|(idx, c)| tile(mask, idx, c))
+        .collect()
+}
+
+/// Render `guess` with each char on a green, purple, or black background
+/// according to `mask`, using ANSI escape codes, like the tiles in the game.
+/// Chars whose color `mask` doesn't say are left uncolored.
+///
+/// This is only readable on a terminal; `feedback_letters` shows the same
+/// information as plain text.
+pub fn render_feedback(guess: &str, mask: &Masks) -> String {
+    let mut out = String::new();
+    for (idx, c) in guess.chars().enumerate() {
+        let c = normalize_power(c);
+        match tile(mask, idx, c) {
+            'G' => out.push_str(&format!("\x1b[97;42m {} \x1b[0m", c)),
+            'P' => out.push_str(&format!("\x1b[97;45m {} \x1b[0m", c)),
+            'B' => out.push_str(&format!("\x1b[97;40m {} \x1b[0m", c)),
+            _ => out.push_str(&format!(" {} ", c)),
+        }
+    }
+    out
+}
+
+/// Compute the same mask as `score`, packed as by `Masks::pack` for the length
+/// of `guess`, without allocating.
+///
+/// Panics if `guess` is more than 16 chars long, or if `truth` has a different
+/// length, as for `score`.
+pub fn score_packed(guess: &str, truth: &str) -> u32 {
+    let row = |s| Row::new(s).expect("can't pack more than 16 positions");
+    row(guess).score(&row(truth))
+}
+
+// The feedback for a guess, as a key which is equal for two solutions exactly
+// when the guess gets the same feedback from both
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum MaskKey {
+    Packed(u32),
+    // For guesses too long to pack
+    Full(Masks),
+}
+
+// Score `guess` against solutions as a `MaskKey`, packing the mask as
+// `score_packed` does when `guess` is short enough, and otherwise falling back
+// to the full mask from `score`
+pub(crate) fn feedback_key(guess: &str) -> impl Fn(&str) -> MaskKey + '_ {
+    let packed = guess.chars().count() <= MAX_PACKED_LEN;
+    move |truth| {
+        if packed {
+            MaskKey::Packed(score_packed(guess, truth))
+        } else {
+            MaskKey::Full(score(guess, truth))
+        }
+    }
+}
+
+// The normalized chars of an equation, so that scoring it many times doesn't
+// need to decode and normalize it each time
+struct Row {
+    chars: [char; MAX_PACKED_LEN],
+    len: usize,
+}
+
+impl Row {
+    // `None` if `s` is too long to pack
+    fn new(s: &str) -> Option<Self> {
+        let mut chars = ['\0'; MAX_PACKED_LEN];
+        let mut len = 0;
+        for c in s.chars() {
+            *chars.get_mut(len)? = normalize_power(c);
+            len += 1;
+        }
+        Some(Row { chars, len })
+    }
+
+    fn as_str(&self) -> String {
+        self.chars[..self.len].iter().collect()
+    }
+
+    // The packed mask for guessing `self` when `truth` is the solution
+    fn score(&self, truth: &Row) -> u32 {
+        let (g, t) = (&self.chars, &truth.chars);
+        assert_eq!(
+            self.len,
+            truth.len,
+            "can't score {} against {} of a different length",
+            self.as_str(),
+            truth.as_str()
+        );
+        let len = self.len;
+
+        // Truth positions which haven't been matched to a guess position yet
+        let mut unused = [false; MAX_PACKED_LEN];
+        let mut packed = 0;
+        for idx in 0..len {
+            if g[idx] == t[idx] {
+                packed |= PACKED_GREEN << (2 * idx);
+            } else {
+                unused[idx] = true;
+            }
+        }
+        for idx in 0..len {
+            if g[idx] == t[idx] {
+                continue;
+            }
+            if let Some(j) = (0..len).find(|&j| unused[j] && t[j] == g[idx]) {
+                unused[j] = false;
+                packed |= PACKED_PURPLE << (2 * idx);
+            }
+        }
+        packed
+    }
+}
+
+// Convert every entry of the corpus to a `Row` up front, for functions which
+// score each guess against the whole corpus, or `None` if any entry is too long
+// to pack
+fn rows<T: AsRef<str> + Sync>(corpus: &[T]) -> Option<Vec<Row>> {
+    corpus.par_iter().map(|v| Row::new(v.as_ref())).collect()
+}
+
+// Evaluate each of `guesses` against the corpus according to `strategy`,
+// scoring with `rows` where the equations can be packed, and otherwise with
+// `Strategy::evaluate`
+fn evaluate_guesses<'a, T, U>(
+    guesses: &'a [U],
+    corpus: &[T],
+    strategy: Strategy,
+) -> Vec<(&'a U, f64)>
+where
+    T: AsRef<str> + Sync,
+    U: AsRef<str> + Sync,
+{
+    let rows = rows(corpus);
+    guesses
         .par_iter()
-        .map(|w| (w.as_ref(), compute_entropy(w.as_ref(), corpus)))
-        .max_by(|a, b| a.1.total_cmp(&b.1))
-        .unwrap()
+        .map(|w| {
+            let metric = match (&rows, Row::new(w.as_ref())) {
+                (Some(rows), Some(row)) => strategy.evaluate_rows(&row, rows),
+                _ => strategy.evaluate(w.as_ref(), corpus),
+            };
+            (w, metric)
+        })
+        .collect()
+}
+
+/// Compute the guess which has the highest entropy in the corpus. If several
+/// guesses are tied, the alphabetically first one is chosen, so the result
+/// doesn't depend on the order of the corpus or how the work is split between
+/// threads. Returns `None` if the corpus is empty.
+pub fn compute_best_guess<T: AsRef<str> + Sync>(corpus: &[T]) -> Option<(&str, f64)> {
+    best_guess(corpus, Strategy::Entropy)
+}
+
+// The result of `compute_best_guess` on each of the full corpora, which is the
+// same every game. Scoring the classic corpus takes over a minute.
+const FIRST_GUESSES: &[(&str, &str)] = &[("micro", "4-1=3"), ("classic", "48-32=16")];
+
+/// The guess `compute_best_guess` picks from the full `"micro"` or `"classic"`
+/// corpus, as generated by `gen`, or `None` for any other corpus. There's no
+/// entry for maxi, since its corpus is far too large to score exhaustively.
+pub fn precomputed_first_guess(corpus_name: &str) -> Option<&'static str> {
+    FIRST_GUESSES
+        .iter()
+        .find(|(name, _)| *name == corpus_name)
+        .map(|(_, guess)| *guess)
+}
+
+/// How to rank guesses against a corpus of candidates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    /// Maximize the information gained about the solution, see
+    /// `compute_entropy`
+    #[default]
+    Entropy,
+    /// Minimize the largest number of candidates which could be left, see
+    /// `compute_best_guess_minimax`
+    Minimax,
+    /// Minimize the average number of candidates left, see
+    /// `expected_remaining`
+    ExpectedRemaining,
+}
+
+impl Strategy {
+    /// The metric for `guess` against the corpus under this strategy
+    pub fn evaluate<T: AsRef<str>>(self, guess: &str, corpus: &[T]) -> f64 {
+        self.evaluate_counts(mask_counts(guess, corpus))
+    }
+
+    // Like `evaluate`, on a corpus which has already been converted by `rows`
+    fn evaluate_rows(self, guess: &Row, rows: &[Row]) -> f64 {
+        self.evaluate_counts(row_mask_counts(guess, rows))
+    }
+
+    // The metric for a guess which splits the candidates into groups of
+    // `counts` candidates sharing a mask
+    pub(crate) fn evaluate_counts(self, counts: impl IntoIterator<Item = usize>) -> f64 {
+        let counts = counts.into_iter();
+        match self {
+            Strategy::Entropy => counts.map(|ct| ct as f64 * -(ct as f64).log2()).sum(),
+            Strategy::Minimax => counts.max().unwrap_or(0) as f64,
+            Strategy::ExpectedRemaining => {
+                let (total, squares) = counts.fold((0, 0), |(t, s), ct| (t + ct, s + ct * ct));
+                if total == 0 {
+                    0.
+                } else {
+                    squares as f64 / total as f64
+                }
+            }
+        }
+    }
+
+    // Order two metrics so that the better one is greater
+    pub(crate) fn compare(self, a: f64, b: f64) -> Ordering {
+        match self {
+            Strategy::Entropy => a.total_cmp(&b),
+            Strategy::Minimax | Strategy::ExpectedRemaining => b.total_cmp(&a),
+        }
+    }
+}
+
+/// Compute the best guess in the corpus according to `strategy`, along with
+/// its metric. Equally good guesses are broken alphabetically, as in
+/// `top_guesses`. Returns `None` if the corpus is empty.
+pub fn best_guess<T: AsRef<str> + Sync>(corpus: &[T], strategy: Strategy) -> Option<(&str, f64)> {
+    evaluate_guesses(corpus, corpus, strategy)
+        .into_iter()
+        .map(|(w, metric)| (w.as_ref(), metric))
+        .max_by(|a, b| strategy.compare(a.1, b.1).then_with(|| b.0.cmp(a.0)))
+}
+
+/// Compute the `n` best guesses in the corpus according to `strategy`, best
+/// first, along with their metric. Guesses which are equally good are ordered
+/// alphabetically.
+pub fn top_guesses<T: AsRef<str> + Sync>(
+    corpus: &[T],
+    n: usize,
+    strategy: Strategy,
+) -> Vec<(&str, f64)> {
+    let mut guesses = evaluate_guesses(corpus, corpus, strategy)
+        .into_iter()
+        .map(|(w, metric)| (w.as_ref(), metric))
+        .collect::<Vec<_>>();
+    guesses.sort_by(|a, b| strategy.compare(b.1, a.1).then_with(|| a.0.cmp(b.0)));
+    guesses.truncate(n);
+    guesses
+}
+
+/// Compute the guess from `guesses` which has the highest entropy against the
+/// `candidates`. Unlike `compute_best_guess`, the guesses don't need to be
+/// possible answers: a guess which can't be right can still narrow down the
+/// candidates more than any which can. Ties are broken alphabetically. Returns
+/// `None` if there are no guesses.
+pub fn compute_best_guess_from<'a, T, U>(guesses: &'a [U], candidates: &[T]) -> Option<(&'a U, f64)>
+where
+    T: AsRef<str> + Sync,
+    U: AsRef<str> + Sync,
+{
+    evaluate_guesses(guesses, candidates, Strategy::Entropy)
+        .into_iter()
+        .max_by(|a, b| {
+            a.1.total_cmp(&b.1)
+                .then_with(|| b.0.as_ref().cmp(a.0.as_ref()))
+        })
+}
+
+/// Choose the next guess when only `remaining_guesses` are left. With one
+/// guess left (or only one candidate), a guess which can't be the solution is
+/// wasted, so this picks the candidate with the highest entropy, as
+/// `compute_best_guess` does. Otherwise it picks from the whole
+/// `allowed_guesses` pool, as `compute_best_guess_from` does. Returns `None` if
+/// there's nothing to choose from.
+pub fn best_guess_with_budget<'a, T, U>(
+    candidates: &'a [T],
+    allowed_guesses: &'a [U],
+    remaining_guesses: usize,
+) -> Option<&'a str>
+where
+    T: AsRef<str> + Sync,
+    U: AsRef<str> + Sync,
+{
+    if remaining_guesses <= 1 || candidates.len() <= 1 {
+        compute_best_guess(candidates).map(|(guess, _)| guess)
+    } else {
+        compute_best_guess_from(allowed_guesses, candidates).map(|(guess, _)| guess.as_ref())
+    }
+}
+
+/// Compute the guess whose largest group of candidates sharing a mask is
+/// smallest, i.e. the guess which minimizes the worst-case number of remaining
+/// candidates. Returns the guess and the size of that largest group. Ties are
+/// broken alphabetically. Returns `None` if the corpus is empty.
+pub fn compute_best_guess_minimax<T: AsRef<str> + Sync>(corpus: &[T]) -> Option<(&str, usize)> {
+    evaluate_guesses(corpus, corpus, Strategy::Minimax)
+        .into_iter()
+        .map(|(w, worst)| (w.as_ref(), worst as usize))
+        .min_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)))
+}
+
+// Count how many entries of the corpus produce each mask for guess `x`, in
+// order of the mask, so that sums over the counts don't depend on the hash
+// order. Masks are compared packed unless `x` is too long to pack.
+fn mask_counts<T: AsRef<str>>(x: &str, corpus: &[T]) -> Vec<usize> {
+    fn count<K: Ord>(masks: impl Iterator<Item = K>) -> Vec<usize> {
+        let mut counts: BTreeMap<K, usize> = BTreeMap::new();
+        for m in masks {
+            *counts.entry(m).or_default() += 1;
+        }
+        counts.into_values().collect()
+    }
+
+    let corpus = corpus.iter().map(|v| v.as_ref());
+    if x.chars().count() <= MAX_PACKED_LEN {
+        count(corpus.map(|v| score_packed(x, v)))
+    } else {
+        count(corpus.map(|v| score(x, v)))
+    }
+}
+
+// Like `mask_counts`, on a corpus which has already been converted by `rows`,
+// returning just the counts in order of the packed mask. Sorting the masks is
+// faster than counting them in a map.
+fn row_mask_counts(x: &Row, rows: &[Row]) -> Vec<usize> {
+    let mut masks = rows.iter().map(|v| x.score(v)).collect::<Vec<_>>();
+    masks.sort_unstable();
+    masks.chunk_by(|a, b| a == b).map(|c| c.len()).collect()
+}
+
+/// Split the corpus by the feedback `guess` would get if each entry were the
+/// solution, returning the number of entries which would give each mask. The
+/// counts add up to the length of the corpus.
+pub fn mask_distribution<T: AsRef<str>>(guess: &str, corpus: &[T]) -> HashMap<Masks, usize> {
+    // Group by packed mask, remembering one solution in each group to score
+    let key = feedback_key(guess);
+    let mut groups: BTreeMap<MaskKey, (&str, usize)> = BTreeMap::new();
+    for v in corpus {
+        let v = v.as_ref();
+        groups.entry(key(v)).or_insert((v, 0)).1 += 1;
+    }
+    groups
+        .into_values()
+        .map(|(truth, ct)| (score(guess, truth), ct))
+        .collect()
+}
+
+/// Every distinct mask `guess` could get, taking each entry of the corpus as
+/// the solution, i.e. the keys of `mask_distribution`.
+pub fn possible_masks<T: AsRef<str>>(guess: &str, corpus: &[T]) -> BTreeSet<Masks> {
+    let key = feedback_key(guess);
+    let mut seen = BTreeMap::new();
+    for v in corpus {
+        let v = v.as_ref();
+        seen.entry(key(v)).or_insert(v);
+    }
+    seen.into_values()
+        .map(|truth| score(guess, truth))
+        .collect()
 }
 
 // Compute the entropy of the given guess `x` against the corpus.
 //
 // Note that we don't subtract out the corpus.len().log2(), since that doesn't
 // change the ordering of the entropies.
+//
+// Since `score` and `matches_mask` agree, the number of corpus entries matching
+// each mask is exactly the number which scored to it, so there's no need to
+// rescan the corpus.
 pub fn compute_entropy<T: AsRef<str>>(x: &str, corpus: &[T]) -> f64 {
-    let mut masks: HashMap<Masks, usize> = HashMap::new();
-    for v in corpus {
-        let m = score(x, v.as_ref());
-        *masks.entry(m).or_default() += 1;
-    }
-
-    let mut t = 0.;
-
-    for (m, ct) in masks {
-        let matching = corpus
-            .iter()
-            .filter(|c| matches_mask(c.as_ref(), &m))
-            .count();
-        let p = -(matching as f64).log2();
+    Strategy::Entropy.evaluate(x, corpus)
+}
 
-        t += ct as f64 * p;
+/// Compute the entropy of guess `x` against a corpus where each candidate has a
+/// weight, such as how likely it is to be the solution. Each mask is as likely
+/// as the total weight of the candidates producing it, so with every weight 1
+/// this is the same as `compute_entropy`.
+pub fn compute_entropy_weighted<T: AsRef<str>>(x: &str, corpus: &[(T, f64)]) -> f64 {
+    let key = feedback_key(x);
+    let mut masks: BTreeMap<MaskKey, f64> = BTreeMap::new();
+    for (v, weight) in corpus {
+        *masks.entry(key(v.as_ref())).or_default() += weight;
     }
-    t
+    masks
+        .into_values()
+        .filter(|w| *w > 0.)
+        .map(|w| w * -w.log2())
+        .sum()
+}
+
+/// Compute the average number of candidates which would remain after guessing
+/// `guess`, if the solution is equally likely to be any entry of the corpus.
+///
+/// A mask shared by `k` entries is seen with probability `k / corpus.len()`
+/// and leaves `k` candidates, so this is the sum of `k² / corpus.len()`.
+pub fn expected_remaining<T: AsRef<str>>(guess: &str, corpus: &[T]) -> f64 {
+    Strategy::ExpectedRemaining.evaluate(guess, corpus)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{compute_best_guess, compute_entropy, score, Masks};
+    use std::time::Instant;
+
+    use super::{
+        best_guess, best_guess_with_budget, check_guess, compute_best_guess,
+        compute_best_guess_from, compute_best_guess_minimax, compute_entropy,
+        compute_entropy_weighted, expected_remaining, explain, explain_mismatch, feedback_letters,
+        mask_distribution, masks_from_string, masks_to_string, matches_mask, merge,
+        parse_mask_results, parse_mask_results_strict, possible_masks, precomputed_first_guess,
+        render_feedback, satisfies_hard_mode, score, score_packed, top_guesses, Masks, Strategy,
+    };
 
     #[test]
     fn test_score() {
-        let m = score("abc", "cbd");
+        let m = score("abd", "dbe");
         assert_eq!(
             m,
             Masks {
                 correct: [(1, 'b')].into_iter().collect(),
-                incorrect: [(2, 'c')].into_iter().collect(),
+                incorrect: [(2, 'd')].into_iter().collect(),
                 not_present: [(0, 'a')].into_iter().collect(),
+                counts: None,
             }
         )
     }
 
+    #[test]
+    #[should_panic(expected = "different length")]
+    fn test_score_length_mismatch() {
+        score("1+1=2", "10-1=9");
+    }
+
+    #[test]
+    #[should_panic(expected = "different length")]
+    fn test_score_packed_length_mismatch() {
+        score_packed("10-1=9", "1+1=2");
+    }
+
+    #[test]
+    fn test_rank_long_equations() {
+        // These are too long to pack, so they're ranked with the full masks
+        let corpus = [
+            "10+2+3+4+5+6+7=37",
+            "1+2+3+4+5+6+17=38",
+            "1+2+3+4+5+16+7=38",
+            "1+2+3+4+15+6+7=38",
+        ];
+        for guess in corpus {
+            let counts = mask_distribution(guess, &corpus);
+            assert_eq!(counts.values().sum::<usize>(), corpus.len());
+            let entropy = counts
+                .values()
+                .map(|&ct| ct as f64 * -(ct as f64).log2())
+                .sum::<f64>();
+            assert!((compute_entropy(guess, &corpus) - entropy).abs() < 1e-9);
+            assert_eq!(
+                possible_masks(guess, &corpus),
+                corpus.iter().map(|truth| score(guess, truth)).collect()
+            );
+        }
+
+        // Every guess tells the others apart, so the alphabetically first is best
+        let (best, _) = compute_best_guess(&corpus).unwrap();
+        assert_eq!(best, "1+2+3+4+15+6+7=38");
+        assert_eq!(top_guesses(&corpus, 1, Strategy::Entropy)[0].0, best);
+        assert_eq!(
+            compute_best_guess_from(&corpus, &corpus).map(|(guess, _)| *guess),
+            Some(best)
+        );
+        assert_eq!(compute_best_guess_minimax(&corpus), Some((best, 1)));
+    }
+
+    #[test]
+    fn test_power_glyphs() {
+        // A guess typed with `s` and `c` against a corpus using ² and ³
+        let corpus = crate::gen::collect_equations(6, true);
+        for (guess, ascii) in [("1+2²=5", "1+2s=5"), ("2³-1=7", "2c-1=7")] {
+            for truth in &corpus {
+                let m = score(guess, truth);
+                assert_eq!(score(ascii, truth), m, "{} against {}", ascii, truth);
+                assert_eq!(score_packed(ascii, truth), m.pack(6));
+                assert_eq!(explain(ascii, truth), explain(guess, truth));
+                assert!(matches_mask(truth, &m));
+                let ascii_truth = truth.replace('²', "s").replace('³', "c");
+                assert_eq!(score(guess, &ascii_truth), m);
+                assert!(matches_mask(&ascii_truth, &m));
+            }
+        }
+
+        let m = score("2s+1=5", "3²-1=8");
+        assert_eq!(m, parse_mask_results("2²+1=5", "BGBGGB").unwrap());
+        assert!(matches_mask("3s-1=8", &m));
+        assert!(!matches_mask("1+2s=5", &m));
+    }
+
+    #[test]
+    fn test_is_solved() {
+        assert!(score("3*3=9", "3*3=9").is_solved(5));
+        assert!(parse_mask_results("12+3=15", "GGGGGGG")
+            .unwrap()
+            .is_solved(7));
+        assert!(!score("3*3=9", "3*3=9").is_solved(7));
+        assert!(!score("3*3=9", "9=3*3").is_solved(5));
+        assert!(!parse_mask_results("12+3=15", "GGGGGGB")
+            .unwrap()
+            .is_solved(7));
+        assert!(!Masks::default().is_solved(5));
+    }
+
+    #[test]
+    fn test_to_gpb() {
+        assert_eq!(score("12+34=46", "12+43=55").to_gpb(8), "GGGPPGBB");
+        assert_eq!(score("3*3=9", "3*3=9").to_gpb(5), "GGGGG");
+        assert_eq!(score("3*3=9", "3*3=9").to_gpb(3), "GGG");
+        assert_eq!(Masks::default().to_gpb(3), "???");
+        let m = parse_mask_results("12+34=46", "GG?PBG??").unwrap();
+        assert_eq!(m.to_gpb(8), "GG?PBG??");
+
+        let corpus = crate::gen::collect_equations(6, true);
+        for guess in corpus.iter().step_by(20) {
+            let len = guess.chars().count();
+            for truth in corpus.iter().step_by(7) {
+                let m = score(guess, truth);
+                assert_eq!(m.to_gpb(len), explain(guess, truth));
+                assert_eq!(parse_mask_results(guess, &m.to_gpb(len)), Some(m));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_mask_unknown() {
+        let corpus = crate::gen::collect_equations(7, false);
+        let guess = "11+1=12";
+
+        // A mask with no known positions rules nothing out
+        let m = parse_mask_results(guess, "???????").unwrap();
+        assert_eq!(m, Masks::default());
+        assert!(corpus.iter().all(|t| matches_mask(t, &m)));
+
+        for truth in corpus.iter().step_by(101) {
+            let full = explain(guess, truth);
+            for idx in 0..full.len() {
+                let mut partial = full.clone();
+                partial.replace_range(idx..idx + 1, "?");
+                let m = parse_mask_results(guess, &partial).unwrap();
+                assert!(m.known_positions().iter().all(|&(i, _)| i != idx));
+                assert!(matches_mask(truth, &m), "{} {} {}", guess, partial, truth);
+
+                // Forgetting one position only adds candidates, and the others
+                // still apply
+                let mut others = partial.clone();
+                others.replace_range(idx..idx + 1, "B");
+                let others = parse_mask_results(guess, &others).unwrap();
+                for t in corpus.iter().step_by(11) {
+                    if matches_mask(t, &score(guess, truth)) {
+                        assert!(matches_mask(t, &m), "{} {} {}", partial, truth, t);
+                    }
+                    if !others.correct().all(|(i, c)| t.chars().nth(i) == Some(c)) {
+                        assert!(!matches_mask(t, &m), "{} {} {}", partial, truth, t);
+                    }
+                }
+            }
+        }
+
+        // The first 1 might be green, so the black 1 doesn't rule it out
+        let m = parse_mask_results("11+1=13", "?BBBGGG").unwrap();
+        assert!(matches_mask("10-7=13", &m));
+        assert!(matches_mask("20-7=13", &m));
+        let black = parse_mask_results("11+1=13", "BBBBGGG").unwrap();
+        assert!(!matches_mask("10-7=13", &black));
+        assert!(!matches_mask("12*1=13", &m));
+        assert_eq!(masks_from_string(&masks_to_string(&m)), Some(m));
+    }
+
+    #[test]
+    fn test_render_feedback() {
+        let m = score("1+2=3", "3*1=3");
+        assert_eq!(feedback_letters("1+2=3", &m), "PBBGG");
+        assert_eq!(
+            feedback_letters("1+2s=5", &score("1+2²=5", "3²-1=8")),
+            "PBBPGB"
+        );
+        let partial = parse_mask_results("1+2=3", "P?BGG").unwrap();
+        assert_eq!(feedback_letters("1+2=3", &partial), "P?BGG");
+
+        assert_eq!(
+            render_feedback("1+2=3", &partial),
+            "\x1b[97;45m 1 \x1b[0m + \x1b[97;40m 2 \x1b[0m\x1b[97;42m = \x1b[0m\x1b[97;42m 3 \x1b[0m"
+        );
+        assert!(render_feedback("2s=4", &score("2²=4", "2²=4")).contains(" ² "));
+    }
+
+    #[test]
+    fn test_equals_position() {
+        // The `=` is at position 4 or 5 in the 7-slot equations
+        let corpus = crate::gen::collect_equations(7, false);
+        let at = |idx| {
+            corpus
+                .iter()
+                .filter(|e| e.find('=') == Some(idx))
+                .collect::<Vec<_>>()
+        };
+        assert!(!at(4).is_empty() && !at(5).is_empty());
+
+        for (guess, truth) in [("12+3=15", "1+2+3=6"), ("1+2+3=6", "99/9=11")] {
+            let m = score(guess, truth);
+            let idx = guess.find('=').unwrap();
+            assert!(m.incorrect().any(|x| x == (idx, '=')));
+            assert!(at(idx).iter().all(|e| !matches_mask(e, &m)));
+            assert!(matches_mask(truth, &m));
+        }
+
+        let m = score("12+3=15", "10+5=15");
+        assert!(m.correct().any(|x| x == (4, '=')));
+        assert!(corpus
+            .iter()
+            .filter(|e| matches_mask(e, &m))
+            .all(|e| e.find('=') == Some(4)));
+
+        // With more `=` signs than the solution, the extra one is black
+        let m = score("1=1=1", "1+1=2");
+        assert_eq!(feedback_letters("1=1=1", &m), "GBGGB");
+    }
+
+    #[test]
+    fn test_mask_distribution() {
+        let corpus = crate::gen::collect_equations(6, true);
+        for guess in ["1+2²=5", "12/3=4", "2³-1=7"] {
+            let dist = mask_distribution(guess, &corpus);
+            assert_eq!(dist.values().sum::<usize>(), corpus.len());
+            assert_eq!(
+                dist.values().copied().max(),
+                Some(Strategy::Minimax.evaluate(guess, &corpus) as usize)
+            );
+            for (m, ct) in &dist {
+                let matching = corpus.iter().filter(|t| matches_mask(t, m)).count();
+                assert_eq!(matching, *ct, "{} {}", guess, feedback_letters(guess, m));
+            }
+        }
+
+        let dist = mask_distribution("1+1=2", &["1+1=2", "2*1=2", "2/1=2", "1*2=2"]);
+        assert_eq!(dist.len(), 3);
+        assert_eq!(dist[&score("1+1=2", "1+1=2")], 1);
+        assert_eq!(dist[&score("1+1=2", "2*1=2")], 2);
+    }
+
+    #[test]
+    fn test_explain_mismatch() {
+        let m = parse_mask_results("12+34=46", "GBGPBGBB").unwrap();
+        for (guess, expected) in [
+            ("13+57=70", None),
+            (
+                "12+35=47",
+                Some("char `2` at position 2 should not be present"),
+            ),
+            (
+                "10+37=57",
+                Some("char `3` at position 4 should be in another position"),
+            ),
+            ("31+70=71", Some("missing required green `1` at position 1")),
+            ("10+57=77", Some("should have at least 1 `3`, but has 0")),
+            ("13+72=20", Some("should have at most 0 `2`, but has 2")),
+        ] {
+            let reason = explain_mismatch(guess, &m);
+            assert_eq!(reason.as_deref(), expected, "{}", guess);
+            assert_eq!(reason.is_none(), matches_mask(guess, &m), "{}", guess);
+        }
+
+        let corpus = crate::gen::collect_equations(6, false);
+        let m = score("4*5=20", "12/3=4");
+        for guess in &corpus {
+            assert_eq!(
+                explain_mismatch(guess, &m).is_none(),
+                matches_mask(guess, &m)
+            );
+        }
+    }
+
+    #[test]
+    fn test_possible_masks() {
+        let corpus = crate::gen::collect_equations(6, false);
+        for guess in ["4*5=20", "12/3=4", "10-3=7"] {
+            let masks = possible_masks(guess, &corpus);
+            let dist = mask_distribution(guess, &corpus);
+            assert_eq!(masks.len(), dist.len());
+            for m in &masks {
+                assert!(dist.contains_key(m));
+                assert!(corpus.iter().any(|t| score(guess, t) == *m));
+            }
+        }
+
+        let empty: [&str; 0] = [];
+        assert!(possible_masks("1+1=2", &empty).is_empty());
+    }
+
+    #[test]
+    fn test_score_duplicates() {
+        // Feedback from the real game, as (guess, truth, mask)
+        for (guess, truth, expected) in [
+            ("11+11=22", "12+12=24", "GBGGBGGP"),
+            ("10+10=20", "99-79=20", "BBBBBGGG"),
+            ("22-11=11", "11+11=22", "PPBGGGPP"),
+            ("1+1+1=3", "1+2*3=7", "GGBBBGP"),
+            ("2*2*2=8", "8/4*2=4", "BBBGGGP"),
+            ("2+22=24", "2*12=24", "GBBGGGG"),
+            ("1+12=13", "12+1=13", "GPPPGGG"),
+        ] {
+            assert_eq!(
+                score(guess, truth),
+                parse_mask_results(guess, expected).unwrap(),
+                "{} against {}",
+                guess,
+                truth
+            );
+        }
+    }
+
+    #[test]
+    fn test_score_matches_mask() {
+        let corpus = crate::gen::collect_equations(5, false);
+        for guess in corpus.iter().step_by(8) {
+            for truth in &corpus {
+                let m = score(guess, truth);
+                for t in &corpus {
+                    assert_eq!(
+                        matches_mask(t, &m),
+                        score(guess, t) == m,
+                        "{} against {} and {}",
+                        guess,
+                        truth,
+                        t
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_mask_repeated_chars() {
+        // Two 3s are purple, so the solution has at least two of them
+        let m = score("33+4=37", "1+3+3=7");
+        assert!(matches_mask("1+3+3=7", &m));
+        assert!(!matches_mask("1+3+0=4", &m));
+
+        // One 1 is purple and the other is black, so there's exactly one
+        let m = score("1+1=2", "2=0+1");
+        assert!(matches_mask("2=0+1", &m));
+        assert!(!matches_mask("2=1+1", &m));
+        assert!(!matches_mask("2=0+0", &m));
+
+        // A green 3 plus a black 3 means no other 3s
+        let m = score("3+3=6", "3*2=6");
+        assert!(matches_mask("3*2=6", &m));
+        assert!(!matches_mask("3*3=9", &m));
+
+        let guesses = ["11+1=12", "3+33=36", "22-2=20", "1+1+1=3", "99/9=11"];
+        let corpus = crate::gen::collect_equations(7, false);
+        for guess in guesses {
+            for truth in corpus.iter().step_by(13) {
+                let m = score(guess, truth);
+                for t in corpus.iter().step_by(7) {
+                    assert_eq!(
+                        matches_mask(t, &m),
+                        score(guess, t) == m,
+                        "{} vs {}",
+                        guess,
+                        t
+                    );
+                }
+                assert!(matches_mask(truth, &m));
+            }
+        }
+    }
+
+    #[test]
+    fn test_precomputed_first_guess() {
+        let corpus = crate::gen::collect_equations(5, false);
+        assert_eq!(
+            precomputed_first_guess("micro"),
+            Some(compute_best_guess(&corpus).unwrap().0)
+        );
+        assert_eq!(precomputed_first_guess("classic"), Some("48-32=16"));
+        assert_eq!(precomputed_first_guess("maxi"), None);
+        assert_eq!(precomputed_first_guess("micro_nerdle"), None);
+    }
+
+    #[test]
+    #[ignore = "scores the full classic corpus against itself"]
+    fn test_precomputed_first_guess_classic() {
+        let corpus = crate::gen::collect_equations(8, false);
+        assert_eq!(
+            precomputed_first_guess("classic"),
+            Some(compute_best_guess(&corpus).unwrap().0)
+        );
+    }
+
+    #[test]
+    fn test_compute_best_guess_minimax() {
+        let corpus = [
+            "eee", "aea", "bbc", "dbb", "aab", "cae", "dde", "dbe", "cec", "aae", "bec", "cad",
+        ];
+        // `cae` spreads the corpus out the most on average, but can leave 3
+        // candidates, while `bec` never leaves more than 2
+        assert_eq!(compute_best_guess(&corpus).unwrap().0, "cae");
+        assert_eq!(compute_best_guess_minimax(&corpus).unwrap(), ("bec", 2));
+    }
+
+    #[test]
+    fn test_compute_best_guess_from() {
+        let candidates = ["ab1", "ab2", "ab3", "ab4"];
+        // Guessing any candidate only tells us whether it was right, but `123`
+        // tells every candidate apart
+        let guesses = ["ab1", "ab2", "123"];
+        assert_eq!(
+            compute_best_guess_from(&guesses, &candidates).unwrap(),
+            (&"123", 0.)
+        );
+        assert_eq!(
+            compute_best_guess(&candidates).unwrap().1,
+            -(3f64.log2() * 3.)
+        );
+    }
+
+    #[test]
+    fn test_best_guess_with_budget() {
+        let candidates = ["ab1", "ab2", "ab3", "ab4"];
+        let allowed = ["ab1", "ab2", "123"];
+        // `123` can't be right, but tells every candidate apart
+        assert_eq!(
+            best_guess_with_budget(&candidates, &allowed, 2),
+            Some("123")
+        );
+        assert_eq!(
+            best_guess_with_budget(&candidates, &allowed, 6),
+            Some("123")
+        );
+        // With one guess left, only a candidate can win
+        assert_eq!(
+            best_guess_with_budget(&candidates, &allowed, 1),
+            Some("ab1")
+        );
+        assert_eq!(best_guess_with_budget(&["ab3"], &allowed, 6), Some("ab3"));
+
+        // Among the candidates, the one which splits the rest most is chosen:
+        // only `eba` tells them all apart
+        let candidates = ["aad", "abd", "aed", "eba"];
+        assert_eq!(
+            best_guess_with_budget(&candidates, &allowed, 1),
+            Some("eba")
+        );
+
+        let empty: [&str; 0] = [];
+        assert_eq!(best_guess_with_budget(&empty, &allowed, 1), None);
+        assert_eq!(best_guess_with_budget(&candidates, &empty, 2), None);
+    }
+
+    #[test]
+    fn test_top_guesses() {
+        let corpus = ["abd", "abc", "aba", "cab"];
+        let top = top_guesses(&corpus, 3, Strategy::Entropy);
+        assert_eq!(top.len(), 3);
+        assert!(top.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert_eq!(top[0].1, compute_best_guess(&corpus).unwrap().1);
+        for (guess, e) in &top {
+            assert_eq!(*e, compute_entropy(guess, &corpus));
+        }
+
+        // Ties are broken alphabetically
+        let top = top_guesses(&["abc", "abd", "aba"], 5, Strategy::Entropy);
+        assert_eq!(top, vec![("aba", -2.), ("abc", -2.), ("abd", -2.)]);
+
+        // Fewer remaining candidates is better
+        let top = top_guesses(&corpus, 4, Strategy::ExpectedRemaining);
+        assert!(top.windows(2).all(|w| w[0].1 <= w[1].1));
+        for (guess, r) in &top {
+            assert_eq!(*r, expected_remaining(guess, &corpus));
+        }
+    }
+
+    #[test]
+    fn test_best_guess() {
+        let corpus = [
+            "eee", "aea", "bbc", "dbb", "aab", "cae", "dde", "dbe", "cec", "aae", "bec", "cad",
+        ];
+        let (guess, e) = best_guess(&corpus, Strategy::Entropy).unwrap();
+        assert_eq!((guess, e), compute_best_guess(&corpus).unwrap());
+        assert_eq!(best_guess(&corpus, Strategy::Minimax).unwrap(), ("bec", 2.));
+
+        let (guess, r) = best_guess(&corpus, Strategy::ExpectedRemaining).unwrap();
+        assert_eq!(r, expected_remaining(guess, &corpus));
+        assert!(corpus.iter().all(|w| expected_remaining(w, &corpus) >= r));
+
+        for strategy in [
+            Strategy::Entropy,
+            Strategy::Minimax,
+            Strategy::ExpectedRemaining,
+        ] {
+            assert_eq!(
+                best_guess(&corpus, strategy).unwrap(),
+                top_guesses(&corpus, 1, strategy)[0]
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_mask_results_strict() {
+        assert_eq!(
+            parse_mask_results_strict("3s=9", "GGGG", 4),
+            parse_mask_results("3²=9", "GGGG")
+        );
+        // The lenient parser takes anything
+        assert!(parse_mask_results("3+3=7", "BBBBB").is_some());
+        assert_eq!(parse_mask_results_strict("3+3=7", "BBBBB", 5), None);
+        assert_eq!(parse_mask_results_strict("3+3=6", "BBBBB", 6), None);
+        assert_eq!(parse_mask_results_strict("3+x=6", "BBBBB", 5), None);
+        assert_eq!(parse_mask_results_strict("3+3+6", "BBBBB", 5), None);
+
+        assert_eq!(check_guess("10-4=6", 6), Ok(()));
+        assert!(check_guess("10-4=6", 8)
+            .unwrap_err()
+            .contains("has 6 chars"));
+        assert!(check_guess("10-4=5", 6)
+            .unwrap_err()
+            .contains("isn't a true"));
+    }
+
+    #[test]
+    fn test_parse_mask_results_emoji() {
+        let letters = parse_mask_results("1+2=3", "BBBGP").unwrap();
+        assert_eq!(
+            parse_mask_results("1+2=3", "⬛⬛⬛🟩🟪"),
+            Some(letters.clone())
+        );
+        assert_eq!(
+            parse_mask_results("1+2=3", "⚫⬜⚫🟢🟣"),
+            Some(letters.clone())
+        );
+        // Tiles copied with a variation selector
+        assert_eq!(
+            parse_mask_results("1+2=3", "⬛\u{fe0f}⬛\u{fe0f}⬛\u{fe0f}🟩🟪"),
+            Some(letters)
+        );
+        // Emoji can be mixed with letters, but the length still has to match
+        assert_eq!(
+            parse_mask_results("3s=9", "🟩GG🟩"),
+            parse_mask_results("3²=9", "GGGG")
+        );
+        assert_eq!(parse_mask_results("1+2=3", "⬛⬛⬛🟩"), None);
+        assert_eq!(parse_mask_results("1+2=3", "⬛⬛⬛🟩🟥"), None);
+    }
+
+    #[test]
+    fn test_explain() {
+        assert_eq!(explain("1+2=3", "3*3=9"), "BBBGP");
+        assert_eq!(explain("3*3=9", "3*3=9"), "GGGGG");
+        // Only one of the guessed 1s can be purple
+        assert_eq!(explain("11+1=12", "2+10=12"), "PBPBGGG");
+
+        let corpus = crate::gen::collect_equations(5, false);
+        for guess in corpus.iter().step_by(5) {
+            for truth in corpus.iter().step_by(7) {
+                let m = parse_mask_results(guess, &explain(guess, truth));
+                assert_eq!(m, Some(score(guess, truth)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_score_packed() {
+        let corpus = crate::gen::collect_equations(5, false);
+        for guess in &corpus {
+            for truth in corpus.iter().step_by(3) {
+                assert_eq!(
+                    score_packed(guess, truth),
+                    score(guess, truth).pack(5),
+                    "{} against {}",
+                    guess,
+                    truth
+                );
+            }
+        }
+        for (guess, truth) in [("1+1=2", "2=1+1"), ("33+4=37", "1+3+3=7"), ("aab", "aba")] {
+            let len = guess.chars().count();
+            assert_eq!(score_packed(guess, truth), score(guess, truth).pack(len));
+        }
+        // Green 1, purple 1, black 1
+        assert_eq!(score_packed("1112", "15+1"), 0b00_00_01_10);
+        assert_eq!(score_packed("2²=4", "2²=4"), 0b10_10_10_10);
+    }
+
+    #[test]
+    fn test_expected_remaining() {
+        // Every entry is told apart
+        assert_eq!(expected_remaining("abc", &["abc", "abd", "xyz"]), 1.);
+        // `abd` and `aba` can't be told apart by `abc`
+        assert_eq!(expected_remaining("abc", &["abc", "abd", "aba"]), 5. / 3.);
+        assert_eq!(expected_remaining::<&str>("abc", &[]), 0.);
+    }
+
+    #[test]
+    fn test_masks_string_round_trip() {
+        let m = score("1+2²=5", "3²-1=8");
+        let s = masks_to_string(&m);
+        assert_eq!(s, "g4:= p0:1 p3:² b1:+ b2:2 b5:5");
+        assert_eq!(masks_from_string(&s), Some(m));
+        assert_eq!(masks_from_string(""), Some(Masks::default()));
+
+        for bad in ["x0:1", "g:1", "ga:1", "g0:", "g0:12", "g01"] {
+            assert_eq!(masks_from_string(bad), None, "{}", bad);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_masks_serde_round_trip() {
+        let m = score("11+11=22", "12+12=24");
+        let json = serde_json::to_string(&m).unwrap();
+        assert!(json.starts_with(r#"{"correct":[[0,"1"],"#));
+        assert_eq!(serde_json::from_str::<Masks>(&json).unwrap(), m);
+    }
+
+    #[test]
+    fn test_masks_accessors() {
+        let m = score("1+2²=5", "3²-1=8");
+        assert_eq!(m.correct().collect::<Vec<_>>(), vec![(4, '=')]);
+        assert_eq!(m.incorrect().collect::<Vec<_>>(), vec![(0, '1'), (3, '²')]);
+        assert_eq!(
+            m.not_present().collect::<Vec<_>>(),
+            vec![(1, '+'), (2, '2'), (5, '5')]
+        );
+        assert_eq!(m.known_positions(), vec![(4, '=')]);
+    }
+
+    #[test]
+    fn test_merge() {
+        // The first guess shows at least two `a`s, and the second shows
+        // exactly two, one of which is at position 0. Together, they only
+        // leave room for the other at position 5.
+        let first = parse_mask_results("xaxxax", "BPBBPB").unwrap();
+        let second = parse_mask_results("ayaayy", "GBPBBB").unwrap();
+        let merged = merge(&[first.clone(), second.clone()]);
+        for (candidate, expected) in [
+            ("azzzza", true),
+            ("azzzzz", false),
+            ("zzzzza", false),
+            ("aazzza", false),
+            ("azzzzy", false),
+        ] {
+            assert_eq!(matches_mask(candidate, &merged), expected, "{}", candidate);
+            assert_eq!(
+                matches_mask(candidate, &first) && matches_mask(candidate, &second),
+                expected,
+                "{}",
+                candidate
+            );
+        }
+
+        // Matching the merged mask is the same as matching every mask
+        let corpus = crate::gen::collect_equations(5, false);
+        let masks = ["1+2=3", "9-8=1", "4*2=8"]
+            .iter()
+            .map(|g| score(g, "1*1=1"))
+            .collect::<Vec<_>>();
+        for i in 0..masks.len() {
+            for j in 0..masks.len() {
+                let merged = merge(&[masks[i].clone(), masks[j].clone()]);
+                for c in &corpus {
+                    assert_eq!(
+                        matches_mask(c, &merged),
+                        matches_mask(c, &masks[i]) && matches_mask(c, &masks[j]),
+                        "{}",
+                        c
+                    );
+                }
+            }
+        }
+        assert_eq!(merge(&[masks[0].clone()]), masks[0]);
+        assert_eq!(merge(&[]), Masks::default());
+
+        assert!(merged.counts.is_some());
+        let s = masks_to_string(&merged);
+        assert!(s.ends_with("n2-2:a n0-0:x n0-0:y"), "{}", s);
+        assert_eq!(masks_from_string(&s), Some(merged));
+    }
+
+    #[test]
+    fn test_satisfies_hard_mode() {
+        let m = parse_mask_results("12+35=47", "GPBBBGBB").unwrap();
+        for (guess, expected) in [
+            ("12+35=47", true),
+            ("19+23=42", true),
+            ("18-22=-4", true),
+            ("21+34=55", false),
+            ("19+34=53", false),
+            ("15-3=122", false),
+        ] {
+            assert_eq!(satisfies_hard_mode(guess, &m), expected, "{}", guess);
+        }
+
+        // Squares are normalized
+        let m = parse_mask_results("3s-2=7", "BGBPPB").unwrap();
+        assert!(satisfies_hard_mode("2²=4+0", &m));
+        assert!(satisfies_hard_mode("2s=4+0", &m));
+        assert!(!satisfies_hard_mode("4s=16", &m));
+        assert!(!satisfies_hard_mode("2+2=4", &m));
+    }
+
     #[test]
     fn test_entropy() {
         let e = compute_entropy("abc", &["abc", "abd", "aba"]);
         assert_eq!(e, -2.)
     }
 
+    #[test]
+    fn test_entropy_matches_rescan() {
+        // The entropy as originally computed, by counting how many entries
+        // match each mask
+        fn rescan(x: &str, corpus: &[String]) -> f64 {
+            let masks = corpus.iter().map(|v| score(x, v)).collect::<Vec<_>>();
+            masks
+                .iter()
+                .map(|m| -(corpus.iter().filter(|c| matches_mask(c, m)).count() as f64).log2())
+                .sum()
+        }
+
+        let corpus = crate::gen::collect_equations(5, false);
+        for guess in corpus.iter().step_by(8) {
+            assert!((compute_entropy(guess, &corpus) - rescan(guess, &corpus)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_entropy_weighted() {
+        let corpus = crate::gen::collect_equations(5, false);
+        let weighted = corpus.iter().map(|c| (c, 1.)).collect::<Vec<_>>();
+        for guess in corpus.iter().step_by(9) {
+            let e = compute_entropy(guess, &corpus);
+            assert!((compute_entropy_weighted(guess, &weighted) - e).abs() < 1e-9);
+        }
+
+        // `bca` splits the candidates up best, but once `bad` is much more
+        // likely to be the solution, it's better to guess it directly
+        let corpus = ["bca", "bab", "bac", "baa", "bad"];
+        let best = |weights: [f64; 5]| {
+            let weighted = corpus.iter().zip(weights).collect::<Vec<_>>();
+            corpus
+                .iter()
+                .max_by(|a, b| {
+                    compute_entropy_weighted(a, &weighted)
+                        .total_cmp(&compute_entropy_weighted(b, &weighted))
+                })
+                .copied()
+                .unwrap()
+        };
+        assert_eq!(best([1.; 5]), "bca");
+        assert_eq!(best([1., 1., 1., 1., 10.]), "bad");
+    }
+
     #[test]
     fn test_compute_best_guess() {
-        let g = compute_best_guess(&["abc", "abd", "aba"]);
+        let g = compute_best_guess(&["abc", "abd", "aba"]).unwrap();
         assert_eq!(g, ("aba", -2.));
     }
+
+    #[test]
+    #[ignore = "benchmark; run in release with --nocapture"]
+    fn bench_best_guess() {
+        let corpus = crate::gen::collect_equations(7, false);
+
+        // Scoring each guess from scratch, converting every entry each time
+        let start = Instant::now();
+        let expected = corpus
+            .iter()
+            .map(|w| (w.as_str(), Strategy::Entropy.evaluate(w, &corpus)))
+            .max_by(|a, b| a.1.total_cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+            .unwrap();
+        let unconverted = start.elapsed();
+
+        let start = Instant::now();
+        let found = compute_best_guess(&corpus).unwrap();
+        let converted = start.elapsed();
+
+        assert_eq!(found, expected);
+        println!(
+            "{} candidates: scoring from strings took {:?}, compute_best_guess took {:?} on {} threads",
+            corpus.len(),
+            unconverted,
+            converted,
+            rayon::current_num_threads()
+        );
+    }
+
+    #[test]
+    fn test_best_guess_tiny_corpus() {
+        let empty: [&str; 0] = [];
+        assert_eq!(compute_best_guess(&empty), None);
+        assert_eq!(compute_best_guess_minimax(&empty), None);
+        assert_eq!(compute_best_guess_from(&empty, &["1+1=2"]), None);
+        for strategy in [
+            Strategy::Entropy,
+            Strategy::Minimax,
+            Strategy::ExpectedRemaining,
+        ] {
+            assert_eq!(best_guess(&empty, strategy), None);
+        }
+
+        let single = ["1+1=2"];
+        assert_eq!(compute_best_guess(&single), Some(("1+1=2", 0.)));
+        assert_eq!(compute_best_guess_minimax(&single), Some(("1+1=2", 1)));
+        assert_eq!(best_guess(&single, Strategy::Minimax), Some(("1+1=2", 1.)));
+    }
+
+    #[test]
+    fn test_best_guess_ties() {
+        // Every guess has the same entropy and worst case, so the alphabetically
+        // first should win whatever order the corpus is in
+        let mut corpus = vec!["abc", "abd", "aba", "abe", "abf", "abg", "abh", "abi"];
+        for i in 0..corpus.len() {
+            corpus.rotate_left(1);
+            if i % 2 == 0 {
+                corpus.reverse();
+            }
+            for _ in 0..5 {
+                assert_eq!(compute_best_guess(&corpus).unwrap().0, "aba");
+                assert_eq!(compute_best_guess_minimax(&corpus).unwrap().0, "aba");
+                assert_eq!(*compute_best_guess_from(&corpus, &corpus).unwrap().0, "aba");
+                for strategy in [
+                    Strategy::Entropy,
+                    Strategy::Minimax,
+                    Strategy::ExpectedRemaining,
+                ] {
+                    assert_eq!(best_guess(&corpus, strategy).unwrap().0, "aba");
+                }
+            }
+        }
+    }
 }
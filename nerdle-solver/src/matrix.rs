@@ -0,0 +1,151 @@
+//! Precomputed scores for solving many games against the same corpus
+
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use crate::mask::{feedback_key, MaskKey, Strategy};
+
+/// The masks for every pair of guess and solution in a fixed list of
+/// candidates, so that ranking guesses doesn't need to call `score_packed` again.
+///
+/// Each mask is stored as a `u16` bucket id, which is only meaningful within
+/// its row: two solutions share a bucket for a guess exactly when the guess
+/// scores the same against both. This takes `2 * n²` bytes for `n`
+/// candidates, which is about 32KB for the 127 five-slot equations, and about
+/// 630MB for the 17,723 classic equations. It's not practical for maxi nerdle,
+/// which would need about 9.5TB.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScoreMatrix {
+    candidates: Vec<String>,
+    buckets: Vec<Vec<u16>>,
+    // The number of distinct buckets in each row
+    widths: Vec<u16>,
+}
+
+impl ScoreMatrix {
+    /// Score every candidate against every other.
+    ///
+    /// Panics if some guess splits the candidates into more than 65,536
+    /// different masks, which can't happen for equations up to 10 slots long.
+    pub fn new(candidates: Vec<String>) -> Self {
+        let (buckets, widths) = candidates
+            .par_iter()
+            .map(|guess| {
+                let key = feedback_key(guess);
+                let mut ids: HashMap<MaskKey, u16> = HashMap::new();
+                let row = candidates
+                    .iter()
+                    .map(|truth| {
+                        let next = ids.len();
+                        *ids.entry(key(truth))
+                            .or_insert_with(|| u16::try_from(next).expect("too many masks"))
+                    })
+                    .collect::<Vec<_>>();
+                (row, ids.len() as u16)
+            })
+            .unzip();
+        ScoreMatrix {
+            candidates,
+            buckets,
+            widths,
+        }
+    }
+
+    /// The candidates, indexed in the same order as the matrix
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    /// The bucket id of the mask for guessing candidate `guess` when the
+    /// solution is candidate `truth`
+    pub fn bucket(&self, guess: usize, truth: usize) -> u16 {
+        self.buckets[guess][truth]
+    }
+
+    /// The metric for guessing candidate `guess`, when the solution is one of
+    /// the candidates in `remaining`. This agrees with `Strategy::evaluate`.
+    pub fn evaluate(&self, guess: usize, remaining: &[usize], strategy: Strategy) -> f64 {
+        let row = &self.buckets[guess];
+        let mut counts = vec![0; self.widths[guess] as usize];
+        for &truth in remaining {
+            counts[row[truth] as usize] += 1;
+        }
+        strategy.evaluate_counts(counts.into_iter().filter(|ct| *ct > 0))
+    }
+
+    /// The best candidate to guess when the solution is one of the candidates
+    /// in `remaining`, along with its metric. Equally good guesses are broken
+    /// by their index.
+    pub fn best_guess(&self, remaining: &[usize], strategy: Strategy) -> Option<(usize, f64)> {
+        remaining
+            .par_iter()
+            .map(|&guess| (guess, self.evaluate(guess, remaining, strategy)))
+            .max_by(|a, b| strategy.compare(a.1, b.1).then_with(|| b.0.cmp(&a.0)))
+    }
+
+    /// The candidates in `remaining` which would give the same mask as
+    /// `truth` does when guessing `guess`
+    pub fn filter(&self, guess: usize, truth: usize, remaining: &[usize]) -> Vec<usize> {
+        let row = &self.buckets[guess];
+        remaining
+            .iter()
+            .copied()
+            .filter(|&t| row[t] == row[truth])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScoreMatrix;
+    use crate::mask::{matches_mask, score, Strategy};
+
+    #[test]
+    fn test_score_matrix() {
+        let corpus = crate::gen::collect_equations(5, false);
+        let matrix = ScoreMatrix::new(corpus.clone());
+        assert_eq!(matrix.candidates(), corpus);
+
+        let all = (0..corpus.len()).collect::<Vec<_>>();
+        for strategy in [
+            Strategy::Entropy,
+            Strategy::Minimax,
+            Strategy::ExpectedRemaining,
+        ] {
+            for (i, guess) in corpus.iter().enumerate() {
+                let expected = strategy.evaluate(guess, &corpus);
+                assert!((matrix.evaluate(i, &all, strategy) - expected).abs() < 1e-9);
+            }
+        }
+
+        let (best, e) = matrix.best_guess(&all, Strategy::Entropy).unwrap();
+        let (guess, expected) = crate::mask::best_guess(&corpus, Strategy::Entropy).unwrap();
+        assert!((e - expected).abs() < 1e-9);
+        let best_e = Strategy::Entropy.evaluate(&corpus[best], &corpus);
+        assert!((best_e - Strategy::Entropy.evaluate(guess, &corpus)).abs() < 1e-9);
+        assert_eq!(matrix.best_guess(&[], Strategy::Entropy), None);
+    }
+
+    #[test]
+    fn test_score_matrix_filter() {
+        let corpus = crate::gen::collect_equations(5, false);
+        let matrix = ScoreMatrix::new(corpus.clone());
+        let all = (0..corpus.len()).collect::<Vec<_>>();
+
+        let (guess, truth) = (3, 40);
+        let m = score(&corpus[guess], &corpus[truth]);
+        let remaining = matrix.filter(guess, truth, &all);
+        assert!(remaining.contains(&truth));
+        assert_eq!(
+            remaining,
+            all.iter()
+                .copied()
+                .filter(|&t| matches_mask(&corpus[t], &m))
+                .collect::<Vec<_>>()
+        );
+        for &t in &remaining {
+            assert_eq!(matrix.bucket(guess, t), matrix.bucket(guess, truth));
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Track the remaining candidates over the course of a game
+//!
+//! Nothing here reads from stdin or prints anything, so it can be driven from
+//! something other than the `filter` binary, e.g. from JS when compiled to
+//! `wasm32-unknown-unknown`.
+
+use rayon::prelude::*;
+
+use crate::mask::{best_guess, matches_mask, merge, parse_mask, score, Masks, Strategy};
+
+/// The candidate solutions which are still consistent with every guess so far.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Solver {
+    candidates: Vec<String>,
+    known: Masks,
+    strategy: Strategy,
+}
+
+impl Solver {
+    /// Start a game where any of `candidates` could be the solution
+    pub fn new(candidates: Vec<String>) -> Self {
+        Solver {
+            candidates,
+            known: Masks::default(),
+            strategy: Strategy::default(),
+        }
+    }
+
+    /// Use `strategy` to pick the `best_guess`, rather than entropy
+    pub fn with_strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Remove the candidates which don't match the feedback `mask` for
+    /// `guess`, in the format accepted by `parse_mask`.
+    ///
+    /// Returns the problem, leaving the candidates unchanged, if the mask can't
+    /// be parsed.
+    pub fn apply(&mut self, guess: &str, mask: &str) -> Result<(), String> {
+        self.apply_masks(parse_mask(guess, mask)?);
+        Ok(())
+    }
+
+    /// Remove the candidates which don't match `m`, e.g. from `score`
+    pub fn apply_masks(&mut self, m: Masks) {
+        let before = self.candidates.len();
+        self.candidates = filter_candidates(std::mem::take(&mut self.candidates), &m);
+        debug!(
+            "{} of {} candidates match the feedback",
+            self.candidates.len(),
+            before
+        );
+        self.known = merge(&[std::mem::take(&mut self.known), m]);
+    }
+
+    /// The candidates which are still possible, in their original order
+    pub fn remaining(&self) -> &[String] {
+        &self.candidates
+    }
+
+    /// The number of candidates which are still possible
+    pub fn remaining_count(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /// The best candidate to guess next, or `None` if no candidates are left
+    pub fn best_guess(&self) -> Option<&str> {
+        best_guess(&self.candidates, self.strategy).map(|(guess, _)| guess)
+    }
+
+    /// Everything revealed by the feedback so far, merged into one mask
+    pub fn known(&self) -> &Masks {
+        &self.known
+    }
+}
+
+/// Keep the candidates which match `mask`, in their original order, checking
+/// them in parallel.
+pub fn filter_candidates(candidates: Vec<String>, mask: &Masks) -> Vec<String> {
+    candidates
+        .into_par_iter()
+        .filter(|c| matches_mask(c, mask))
+        .collect()
+}
+
+/// Solve a game by always guessing the best remaining candidate, asking
+/// `oracle` for the feedback to each guess, e.g. by `score`-ing it against the
+/// hidden solution.
+///
+/// Returns the guesses in order. This ends with the solution once the oracle
+/// gives an all-green mask, unless the feedback rules out every candidate. A
+/// guess which isn't solved is never guessed again, even if the oracle's mask
+/// doesn't rule it out, so this always terminates.
+pub fn solve<F: FnMut(&str) -> Masks>(corpus: &[String], mut oracle: F) -> Vec<String> {
+    let mut solver = Solver::new(corpus.to_vec());
+    let mut guesses = vec![];
+    while let Some(guess) = solver.best_guess() {
+        let guess = guess.to_string();
+        let m = oracle(&guess);
+        if m.is_solved(guess.chars().count()) {
+            guesses.push(guess);
+            break;
+        }
+        solver.apply_masks(m);
+        solver.candidates.retain(|c| *c != guess);
+        guesses.push(guess);
+    }
+    guesses
+}
+
+/// How many guesses it took to solve each game in `simulate`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SolveStats {
+    /// The average number of guesses, including the final correct one
+    pub mean: f64,
+    /// The most guesses any game took
+    pub max: usize,
+    /// The number of games which took each number of guesses, indexed by the
+    /// number of guesses
+    pub histogram: Vec<usize>,
+}
+
+/// Play a game for every possible solution in the corpus, starting with
+/// `first_guess` and then always guessing the best remaining candidate
+/// according to `strategy`, and count how many guesses each game takes.
+pub fn simulate(corpus: &[String], strategy: Strategy, first_guess: &str) -> SolveStats {
+    let guesses = corpus
+        .par_iter()
+        .map(|truth| {
+            let mut solver = Solver::new(corpus.to_vec());
+            let mut guess = first_guess.to_string();
+            let mut n = 1;
+            // The solution always remains a candidate, and every other
+            // candidate which is guessed is removed, so this terminates
+            while guess != *truth {
+                solver.apply_masks(score(&guess, truth));
+                guess = best_guess(solver.remaining(), strategy)
+                    .unwrap()
+                    .0
+                    .to_string();
+                n += 1;
+            }
+            n
+        })
+        .collect::<Vec<usize>>();
+
+    let mut stats = SolveStats {
+        max: guesses.iter().copied().max().unwrap_or(0),
+        ..Default::default()
+    };
+    if !guesses.is_empty() {
+        stats.mean = guesses.iter().sum::<usize>() as f64 / guesses.len() as f64;
+    }
+    stats.histogram = vec![0; stats.max + 1];
+    for n in guesses {
+        stats.histogram[n] += 1;
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::{filter_candidates, simulate, solve, SolveStats, Solver};
+    use crate::mask::{Masks, Strategy};
+
+    #[test]
+    fn test_solver() {
+        let mut solver = Solver::new(crate::gen::collect_equations(5, false));
+        assert_eq!(solver.remaining_count(), 127);
+        assert_eq!(
+            solver.best_guess(),
+            crate::mask::compute_best_guess(solver.remaining()).map(|(g, _)| g)
+        );
+
+        // The solution is 3*3=9
+        assert_eq!(solver.apply("1+2=3", "BBBGP"), Ok(()));
+        assert!(solver.remaining().contains(&"3*3=9".to_string()));
+        assert!(solver.remaining_count() < 127);
+
+        assert_eq!(solver.apply("4*2=8", "BGBGB"), Ok(()));
+        assert_eq!(solver.apply("9/3=3", "PBGGP"), Ok(()));
+        assert_eq!(solver.remaining(), ["3*3=9"]);
+        assert_eq!(solver.best_guess(), Some("3*3=9"));
+        assert!(crate::mask::satisfies_hard_mode("3*3=9", solver.known()));
+        assert!(!crate::mask::satisfies_hard_mode("9/3=3", solver.known()));
+    }
+
+    #[test]
+    fn test_solver_bad_mask() {
+        let mut solver = Solver::new(vec!["1+1=2".to_string(), "2*1=2".to_string()]);
+        assert!(solver.apply("1+1=2", "GGG").is_err());
+        assert!(solver.apply("1+1=2", "GGXGG").is_err());
+        assert_eq!(solver.remaining_count(), 2);
+
+        assert_eq!(solver.apply("1+1=2", "BBBBB"), Ok(()));
+        assert_eq!(solver.best_guess(), None);
+    }
+
+    #[test]
+    fn test_filter_candidates() {
+        let corpus = crate::gen::collect_equations(7, true);
+        for guess in ["12+3=15", "2³+1²=9", "99/9=11"] {
+            for truth in corpus.iter().step_by(997) {
+                let m = crate::mask::score(guess, truth);
+                let mut expected = corpus.clone();
+                expected.retain(|c| crate::mask::matches_mask(c, &m));
+                assert_eq!(filter_candidates(corpus.clone(), &m), expected);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark; run in release with --nocapture"]
+    fn bench_filter_candidates() {
+        let corpus = crate::gen::collect_equations(9, true);
+        let m = crate::mask::score(&corpus[0], &corpus[corpus.len() / 2]);
+
+        let start = Instant::now();
+        let mut expected = corpus.clone();
+        expected.retain(|c| crate::mask::matches_mask(c, &m));
+        let serial = start.elapsed();
+
+        let start = Instant::now();
+        let found = filter_candidates(corpus, &m);
+        let parallel = start.elapsed();
+
+        assert_eq!(found, expected);
+        println!(
+            "{} candidates left: retain took {:?}, filter_candidates took {:?} on {} threads",
+            found.len(),
+            serial,
+            parallel,
+            rayon::current_num_threads()
+        );
+    }
+
+    #[test]
+    fn test_solve() {
+        let corpus = crate::gen::collect_equations(5, false);
+        for truth in corpus.iter().step_by(11) {
+            let guesses = solve(&corpus, |g| crate::mask::score(g, truth));
+            assert_eq!(guesses.last(), Some(truth));
+            // Every guess is a candidate, so none is repeated
+            let mut unique = guesses.clone();
+            unique.sort();
+            unique.dedup();
+            assert_eq!(unique.len(), guesses.len());
+        }
+
+        // Feedback which matches nothing stops the game
+        let impossible = crate::mask::parse_mask_results("1+1=2", "PPPPP").unwrap();
+        assert_eq!(solve(&corpus, |_| impossible.clone()).len(), 1);
+        assert!(solve(&[], |g| crate::mask::score(g, "1+1=2")).is_empty());
+
+        // Feedback which rules nothing out still ends once every candidate
+        // has been guessed
+        let corpus = ["1+2=3", "2+1=3", "1+3=4"].map(String::from);
+        let unknown = solve(&corpus, |g| crate::mask::parse_mask(g, "?????").unwrap());
+        assert_eq!(unknown.len(), corpus.len());
+        assert_eq!(solve(&corpus, |_| Masks::default()), unknown);
+    }
+
+    #[test]
+    fn test_simulate() {
+        let corpus = crate::gen::collect_equations(5, false);
+        let stats = simulate(&corpus, Strategy::Entropy, "1+2=3");
+        assert_eq!(stats.histogram.iter().sum::<usize>(), corpus.len());
+        assert_eq!(stats.histogram.len(), stats.max + 1);
+        assert_eq!(stats.histogram[0], 0);
+        // Only the first guess itself is solved in one
+        assert_eq!(stats.histogram[1], 1);
+        assert!(stats.mean > 1. && stats.mean <= stats.max as f64);
+        let total = stats
+            .histogram
+            .iter()
+            .enumerate()
+            .map(|(n, ct)| n * ct)
+            .sum::<usize>();
+        assert!((stats.mean - total as f64 / corpus.len() as f64).abs() < 1e-9);
+
+        let stats = simulate(&[], Strategy::Entropy, "1+2=3");
+        assert_eq!(
+            stats,
+            SolveStats {
+                mean: 0.,
+                max: 0,
+                histogram: vec![0]
+            }
+        );
+    }
+}